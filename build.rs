@@ -16,6 +16,7 @@ use clap::CommandFactory;
 use clap_complete::{generate_to, Shell};
 use clap_mangen::Man;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::{env, fs, io};
 
 #[path = "src/cli.rs"]
@@ -26,6 +27,8 @@ mod cli;
 fn main() -> io::Result<()> {
     println!("cargo:rerun-if-changed=src/cli.rs");
 
+    set_version();
+
     let out_dir = PathBuf::from(env::var_os("OUT_DIR").ok_or(io::ErrorKind::NotFound)?);
 
     generate_man(&out_dir)?;
@@ -34,15 +37,65 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
+/// Export `XSHE_VERSION`, used by the compiled `xshe`'s `Cli` for `--version` and the
+/// `xshe manpage` subcommand.
+///
+/// For release builds this is just `CARGO_PKG_VERSION`. For development builds, it's the
+/// output of `git describe`, so a build always knows exactly which commit it came from.
+///
+/// The `xshe.1` that `generate_man` writes is rendered from this build script's own copy of
+/// `Cli`, which is compiled before this value exists, so it carries `CARGO_PKG_VERSION`
+/// rather than the `git describe` version.
+fn set_version() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let version = if env::var("PROFILE").as_deref() == Ok("release") {
+        None
+    } else {
+        Command::new("git")
+            .args(["describe", "--tags", "--always", "--broken"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|version| version.trim().to_string())
+    }
+    .unwrap_or_else(|| env::var("CARGO_PKG_VERSION").unwrap());
+
+    println!("cargo:rustc-env=XSHE_VERSION={}", version);
+}
+
 fn generate_completion(out_dir: &Path) -> io::Result<()> {
-    // We generate Elvish shell completion for anyone who wants to manually install it,
-    // but Homebrew is unable to install Elvish shell completion.
-    let shells = &[Shell::Bash, Shell::Elvish, Shell::Fish, Shell::Zsh];
+    // We generate Elvish and PowerShell shell completion for anyone who wants to manually
+    // install it, but Homebrew is unable to install either of them.
+    let shells = &[
+        Shell::Bash,
+        Shell::Elvish,
+        Shell::Fish,
+        Shell::PowerShell,
+        Shell::Zsh,
+    ];
 
     for shell in shells {
         let mut cmd = Cli::command();
         generate_to(*shell, &mut cmd, "xshe", out_dir)?;
     }
+
+    #[cfg(feature = "fig")]
+    generate_fig(out_dir)?;
+
+    Ok(())
+}
+
+/// Generate a Fig autocomplete spec, for macOS Fig/Amazon Q users.
+///
+/// This is feature-gated behind the `fig` feature, since `clap_complete_fig` is an extra
+/// build-dependency that most users won't need.
+#[cfg(feature = "fig")]
+fn generate_fig(out_dir: &Path) -> io::Result<()> {
+    let mut cmd = Cli::command();
+    clap_complete::generate_to(clap_complete_fig::Fig, &mut cmd, "xshe", out_dir)?;
     Ok(())
 }
 
@@ -13,14 +13,23 @@
 
 //! Defines the CLI interface for Xshe.
 
-use clap::{ArgGroup, Parser, ValueEnum, ValueHint};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum, ValueHint};
 use clap_verbosity_flag::{Verbosity, WarnLevel};
 use std::path::PathBuf;
 
 // CLI Parser.
 #[derive(Parser, Debug)]
-#[command(about, long_about, author, version)]
+// `option_env!` instead of `env!`: `build.rs` includes this file as its own module (to reuse
+// `Cli` for man/completion generation), and at that point in the build `XSHE_VERSION` hasn't
+// been emitted yet - `env!` would fail to compile the build script outright. Falling back to
+// `CARGO_PKG_VERSION` only matters for that build-script compilation anyway, since the real
+// crate always has `XSHE_VERSION` set by the time it's compiled (see `build.rs`'s `set_version`).
+#[command(about, long_about, author, version = option_env!("XSHE_VERSION").unwrap_or(env!("CARGO_PKG_VERSION")))]
 #[command(arg_required_else_help = true, group = ArgGroup::new("mode").multiple(false))]
+// A subcommand isn't a registered arg/group id, so `shell`'s `required_unless_present_any` can't
+// name it directly - `subcommand_negates_reqs` is clap's own mechanism for "not required once a
+// subcommand is given", applied crate-wide here since `shell` is the only required arg anyway.
+#[command(subcommand_negates_reqs = true)]
 /// Cross-Shell Environment Variable Manager
 ///
 /// Xshe sets shell environment variables across multiple shells with a single configuration file.
@@ -30,31 +39,45 @@ use std::path::PathBuf;
 ///
 /// Source Repository: https://github.com/superatomic/xshe
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// The shell to generate a script for
     ///
     /// Outputs a runnable shell script for the specified shell.
     ///
     /// You can directly source these files in your shell.
     /// See <https://xshe.superatomic.dev/#/cli> for a detailed explanation.
-    #[arg(value_enum, index = 1)]
-    pub shell: Shell,
+    ///
+    /// Not required when --check or --dotenv is passed, since neither generates a script for any
+    /// particular shell.
+    #[arg(value_enum, index = 1, required_unless_present_any = ["check", "dotenv"])]
+    pub shell: Option<Shell>,
 
     #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
-    #[arg(env = "XSHE_CONFIG", group = "mode")]
+    #[arg(env = "XSHE_CONFIG", group = "mode", action = clap::ArgAction::Append)]
     /// Specifies a custom location to read from
     ///
+    /// Can be passed more than once to layer several files together, a shared base config
+    /// followed by a small per-host override, with later files winning conflicts on a
+    /// per-variable (not whole-table) basis. A file can also pull in more files of its own via a
+    /// top-level `include = ["other.toml"]` key; relative include paths resolve against the
+    /// directory of the file that names them, and include cycles are rejected.
+    ///
     /// This defaults to $XDG_CONFIG_HOME, or ~/.config if not set.
     ///
     /// Use --pipe or --file=- to pipe from stdin.
     ///
-    /// The file must be in TOML format (https://toml.io/en/).")
-    pub file: Option<PathBuf>,
+    /// Each file defaults to TOML format (https://toml.io/en/), unless its extension or --format
+    /// says otherwise; see --format for the full list of supported formats.")
+    pub file: Vec<PathBuf>,
 
     #[arg(short, long, value_name = "TOML", value_hint = ValueHint::Other)]
     #[arg(visible_alias = "toml", group = "mode")]
     /// Directly specify TOML to parse
     ///
-    /// The passed string must be in TOML format (https://toml.io/en/).
+    /// The passed string must be in TOML format (https://toml.io/en/), unless --format says
+    /// otherwise.
     pub text: Option<String>,
 
     #[arg(short, long, value_name = "PIPE", verbatim_doc_comment)]
@@ -65,16 +88,157 @@ pub struct Cli {
     ///
     ///     cat xshe.toml | xshe bash
     ///
-    /// The passed string must be in TOML format (https://toml.io/en/).
+    /// The piped data must be in TOML format (https://toml.io/en/), unless --format says
+    /// otherwise.
     pub pipe: bool,
 
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    /// The serialization format the config is written in
+    ///
+    /// If not set, this is auto-detected from the file extension (`.toml`, `.json`, `.yaml`/
+    /// `.yml`) when reading from --file, falling back to TOML for an unrecognized extension.
+    /// Defaults to TOML for --text and --pipe, since there's no file extension to go by.
+    ///
+    /// Whichever format is used, the resulting variables and generated script are identical.
+    pub format: Option<ConfigFormat>,
+
+    #[arg(long, value_name = "NAME", env = "XSHE_PROFILE")]
+    /// Select an `[env.NAME]` overlay of variables
+    ///
+    /// The config file's base variables are generated as usual, then overwritten key-by-key by
+    /// the chosen `[env.NAME]` table, so the profile's values win without needing to repeat the
+    /// variables it doesn't change.
+    ///
+    /// It is an error to pass a name with no matching `[env.NAME]` table.
+    pub profile: Option<String>,
+
+    #[arg(long, value_enum, value_name = "MODE")]
+    /// Eagerly resolve `~` and relative paths at generation time
+    ///
+    /// By default, a value like "~/bin" or "./local/bin" is left for the target shell to
+    /// resolve when the generated script runs. Passing this flag instead resolves it immediately
+    /// into a concrete absolute path, baked directly into the generated script.
+    ///
+    /// "absolutize" never touches the filesystem, so it also works for paths that don't exist
+    /// yet. "canonicalize" does the same, but also resolves symlinks, so the path must exist.
+    pub resolve_paths: Option<PathResolutionMode>,
+
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    /// Write the generated script to FILE instead of the standard output
+    pub output: Option<PathBuf>,
+
+    #[arg(long)]
+    /// Validate the config without generating a script
+    ///
+    /// Parses the config the same way normal generation would, then reports every unknown shell
+    /// or OS named in a shell-conditional value and every malformed interpolation it can find,
+    /// instead of stopping at the first problem. Exits non-zero if any were found, so this pairs
+    /// naturally with -v/-q to control how much detail CI sees when linting an xshe.toml.
+    ///
+    /// Duplicate keys are not checked separately: they are left to the config format's own
+    /// parser, and a TOML file with one fails to load (reporting only the first) before any
+    /// checking starts.
+    pub check: bool,
+
+    #[arg(long, conflicts_with = "shell")]
+    /// Generate a plain .env file instead of a shell script
+    ///
+    /// Emits `KEY=value` lines, quoted using dotenv conventions, for the broad ecosystem of
+    /// dotenv loaders (language libraries, Docker --env-file, etc.) that don't speak
+    /// Bash/Zsh/Fish directly. `$VAR` references are left for the loader to expand, since most
+    /// of them do their own variable substitution; values using command substitution, arithmetic
+    /// expansion, or `~` have no dotenv equivalent and are rejected.
+    ///
+    /// A shell-conditional value (`FOO.bash = "..."`) has no shell to target here, so only its
+    /// `_` catch-all entry, if any, is used; other per-shell entries are ignored.
+    pub dotenv: bool,
+
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["dotenv", "check"])]
+    /// Dump the fully-resolved variables for `shell` as structured data, instead of a script
+    ///
+    /// Serializes the same name -> value mapping `to_shell_source` would render into shell
+    /// syntax - after a `Specific` table has been narrowed down to the single value matching
+    /// `shell`, but before any shell-specific escaping or syntax is applied - as TOML, JSON, or
+    /// YAML. Meant for editor tooling, or for inspecting exactly how a config resolves without
+    /// diffing generated shell scripts by hand.
+    ///
+    /// Still requires `shell`, since a `Specific` table's resolution depends on it.
+    pub dump: Option<ConfigFormat>,
+
     #[clap(flatten)]
     pub verbose: Verbosity<WarnLevel>,
 }
 
+/// The serialization format a config file or string is written in.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+/// How eagerly `~` and relative paths should be resolved when generating a shell script.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PathResolutionMode {
+    /// Lexically resolve, without touching the filesystem.
+    Absolutize,
+    /// Resolve, also following symlinks via the filesystem.
+    Canonicalize,
+}
+
 #[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Shell {
     Bash,
+    /// csh/tcsh. tcsh is a superset of csh, and the two are indistinguishable from the
+    /// perspective of the generated script, so one variant covers both.
+    #[value(name = "csh", alias = "tcsh")]
+    Csh,
     Fish,
+    /// Nushell.
+    #[value(name = "nu", alias = "nushell")]
+    Nushell,
+    PowerShell,
+    /// POSIX `sh` (e.g. dash). Almost everything it generates is identical to bash/zsh, except
+    /// `export -n`, which isn't a POSIX builtin and isn't implemented by every `/bin/sh`.
+    Sh,
     Zsh,
 }
+
+/// Subcommands that don't generate environment variable scripts.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generate a shell completion script
+    ///
+    /// Prints a completion script for the given shell to standard output.
+    /// This can be regenerated at any time, so it doesn't depend on build-time artifacts.
+    ///
+    ///     xshe completions zsh > ~/.zfunc/_xshe
+    Completions {
+        /// The shell to generate a completion script for
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Generate the xshe man page
+    ///
+    /// Prints a roff-formatted man page for xshe to standard output.
+    ///
+    ///     xshe manpage > /usr/local/share/man/man1/xshe.1
+    Manpage,
+
+    /// Suggest variable names from the loaded config file
+    ///
+    /// Prints the variable names defined in the config that start with WORD, one per line, as
+    /// completion candidates. The scripts printed by `xshe completions` do not call this.
+    #[command(hide = true)]
+    CompleteVars {
+        /// The partial variable name already typed at the cursor
+        #[arg(default_value = "")]
+        word: String,
+
+        /// The config file to read variable names from
+        #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        #[arg(env = "XSHE_CONFIG")]
+        file: Option<PathBuf>,
+    },
+}
@@ -0,0 +1,42 @@
+// Copyright 2022 Ethan Kinnear
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Dynamic shell-completion support for variable names defined in the loaded config file.
+//!
+//! Backs the hidden `complete-vars` subcommand, which prints the matching variable names for a
+//! completion script to consume. The scripts printed by `xshe completions` (and generated by
+//! `build.rs`) are clap's static completions and do not call `complete-vars`. Any problem
+//! reading or parsing the config just means no candidates are printed.
+
+use crate::cli::ConfigFormat;
+use crate::structure::ConfigFile;
+use std::path::Path;
+
+/// Prints the names of variables defined in the config at `config_path` that start with
+/// `current_word`, one per line, for a shell completion script to consume.
+///
+/// Degrades gracefully: if the config file is missing or isn't valid TOML, nothing is printed,
+/// so the completion request still exits successfully instead of erroring out in the shell.
+pub(crate) fn complete_variable_names(config_path: &Path, current_word: &str) {
+    let Ok(toml_string) = std::fs::read_to_string(config_path) else {
+        return;
+    };
+    let Ok(config) = ConfigFile::load(toml_string, ConfigFormat::Toml) else {
+        return;
+    };
+    for name in config.vars.keys() {
+        if name.starts_with(current_word) {
+            println!("{}", name);
+        }
+    }
+}
@@ -19,60 +19,327 @@
 #![allow(clippy::ptr_arg)]
 
 mod parser;
+mod path_resolution;
+
 use clap::ValueEnum;
 use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::exit;
 use std::string::String;
 
+pub(crate) use crate::cli::PathResolutionMode;
+
 use crate::cli::Shell::{self, *};
-use crate::convert::parser::ValuePartKind;
-use crate::structure::{EnvVariableOption, EnvVariableValue, EnvironmentVariables};
+use crate::convert::parser::{Expansion, ExpansionOperator, ValuePart, ValuePartKind};
+use crate::structure::{
+    AliasOption, ConfigFile, EnvVariableOption, EnvVariableValue, EnvironmentVariables, OsOption,
+    PathModify,
+};
 
 /// Converts the hash table of `vars` into a script for the given `shell`.
-pub(crate) fn to_shell_source(vars: &EnvironmentVariables, shell: &Shell) -> String {
+///
+/// `resolve_paths`, if set, eagerly resolves `~`/relative values (see `path_resolution`)
+/// against `base_dir` instead of leaving that to the target shell.
+///
+/// Since `vars` is an `IndexMap`, this processes entries in file order, building up a table of
+/// already-rendered values as it goes - so a later value can reference an earlier one by name
+/// (`CARGO_HOME = "${XDG_DATA_HOME}/cargo"`) and get the value this config itself set, not
+/// whatever happens to be in the process environment. See `process_variable` and `render_part`,
+/// which look up and splice in this table's entries.
+///
+/// Not supported for Nushell, since its rendered values aren't self-quoting fragments that can be
+/// spliced into another one (see `render_part`'s own note on this), nor for `Array`/`Path`/
+/// `PathModify` values, which have no single literal form to reference.
+pub(crate) fn to_shell_source(
+    vars: &EnvironmentVariables,
+    shell: &Shell,
+    resolve_paths: Option<PathResolutionMode>,
+    base_dir: &Path,
+) -> String {
+    let mut resolved_values: HashMap<String, String> = HashMap::new();
+    let outputs: Vec<String> = resolve_for_shell(vars, shell)
+        .iter()
+        .map(|(name, raw_value)| {
+            process_variable(
+                shell,
+                name,
+                raw_value,
+                resolve_paths,
+                base_dir,
+                &mut resolved_values,
+            )
+        })
+        .collect();
+    outputs.join("\n") + "\n"
+}
+
+/// Resolve `vars` against `shell`, without formatting anything into shell syntax yet: each
+/// `EnvVariableOption::General` entry passes through unchanged, and each `Specific` table is
+/// narrowed down to the single value matching `shell` and the running OS, dropping entries that
+/// define nothing for it at all. This is the same filtering `to_shell_source` renders from,
+/// exposed separately so `--dump` can inspect it directly instead of diffing generated shell
+/// scripts.
+pub(crate) fn resolve_for_shell(
+    vars: &EnvironmentVariables,
+    shell: &Shell,
+) -> IndexMap<String, EnvVariableValue> {
+    vars.iter()
+        .filter_map(|(name, variable_option)| {
+            let value = match variable_option {
+                EnvVariableOption::General(v) => Some(v),
+                EnvVariableOption::Specific(map) => resolve_specific(shell, map),
+            }?;
+            Some((name.clone(), value.clone()))
+        })
+        .collect()
+}
+
+/// Narrow an `EnvVariableOption::Specific` table down to the single value matching `shell` and
+/// the running OS (`std::env::consts::OS`).
+///
+/// A variable can be specialized by shell (`FOO.bash = "..."`), by OS alone as a sibling of the
+/// shell keys (`FOO.macos = "..."`), or by shell-then-OS (`FOO.bash.macos = "..."`) - checked in
+/// that order, each level falling back to its own `_` catch-all if the running shell/OS isn't
+/// named explicitly.
+fn resolve_specific<'a>(
+    shell: &Shell,
+    map: &'a IndexMap<String, OsOption>,
+) -> Option<&'a EnvVariableValue> {
+    let os = std::env::consts::OS;
+    let binding = shell.to_possible_value()?;
+    let shell_name = binding.get_name();
+    let option = map.get(shell_name).or_else(|| map.get(os)).or_else(|| map.get("_"))?;
+    match option {
+        OsOption::General(value) => Some(value),
+        OsOption::Specific(os_map) => os_map.get(os).or_else(|| os_map.get("_")),
+    }
+}
+
+/// Converts `vars` into a plain `.env` file - `KEY=value` lines, dotenv-quoted - instead of a
+/// shell-specific `export`/`set` script, as used by `--dotenv`.
+///
+/// `EnvVariableOption::Specific` has no shell to target here, so only its `_` catch-all entry (if
+/// any) is used, further narrowed by the running OS like `resolve_specific` does; other per-shell
+/// entries are ignored, since a dotenv file isn't tied to a shell.
+pub(crate) fn to_dotenv_source(vars: &EnvironmentVariables) -> String {
+    let os = std::env::consts::OS;
     let outputs: Vec<String> = vars
         .iter()
         .filter_map(|(name, variable_option)| {
-            // Check whether the current item is a single environment var or a table of
-            // specific shells.
-            match variable_option {
+            let value = match variable_option {
                 EnvVariableOption::General(v) => Some(v),
-                // If it is a shell specific choice, get the correct value for `shell`,
-                // and then extract the `EnvVariableValue` if it exists and skip the value
-                // if it does not
-                EnvVariableOption::Specific(map) => value_for_specific(shell, map),
-            }
-            .map(|raw_value| process_variable(shell, name, raw_value))
+                EnvVariableOption::Specific(map) => match map.get("_") {
+                    Some(OsOption::General(value)) => Some(value),
+                    Some(OsOption::Specific(os_map)) => {
+                        os_map.get(os).or_else(|| os_map.get("_"))
+                    }
+                    None => None,
+                },
+            };
+            value.and_then(|value| process_dotenv_variable(name, value))
         })
         .collect();
     outputs.join("\n") + "\n"
 }
 
-fn process_variable(shell: &Shell, name: &str, raw_value: &EnvVariableValue) -> String {
+fn process_dotenv_variable(name: &str, raw_value: &EnvVariableValue) -> Option<String> {
+    let value = match raw_value {
+        // Dotenv has no unset line, so a `false` value contributes nothing at all.
+        EnvVariableValue::Set(false) => return None,
+        EnvVariableValue::Set(true) => r#""1""#.to_string(),
+        EnvVariableValue::String(string) => dotenv_value(string, name),
+        EnvVariableValue::Array(array_of_arrays) => {
+            dotenv_join(array_of_arrays.iter().flat_map(|array| array.iter()), name)
+        }
+        EnvVariableValue::Path(path) => dotenv_join(path.iter(), name),
+        EnvVariableValue::PathModify(PathModify { prepend, append }) => {
+            let mut parts: Vec<String> =
+                prepend.iter().map(|value| dotenv_value(value, name)).collect();
+            parts.push(format!("${}", name));
+            parts.extend(append.iter().map(|value| dotenv_value(value, name)));
+            parts.join(":")
+        }
+        // Most dotenv loaders already default to leaving an existing key alone (see e.g. the
+        // `dotenv` npm package), which is exactly the guard `Default` exists for - so it's
+        // emitted here as the plain literal value, with no wrapper needed.
+        EnvVariableValue::Default { value } => dotenv_value(value, name),
+        EnvVariableValue::Scoped { value: Some(value), .. } => dotenv_value(value, name),
+        EnvVariableValue::Scoped { value: None, .. } => return None,
+        EnvVariableValue::Integer(integer) => format!(r#""{}""#, integer),
+        EnvVariableValue::Float(float) => format!(r#""{}""#, float),
+        EnvVariableValue::Datetime(datetime) => format!(r#""{}""#, datetime),
+    };
+    Some(format!("{}={}", name, value))
+}
+
+fn dotenv_join<'a>(values: impl Iterator<Item = &'a String>, name: &str) -> String {
+    values.map(|value| dotenv_value(value, name)).collect::<Vec<String>>().join(":")
+}
+
+/// Parse and render a single TOML string value as a dotenv-quoted fragment.
+///
+/// Unlike `expand_value`, there's no shell left to run anything dynamic at load time: a `$VAR`
+/// reference is emitted verbatim, since most dotenv loaders perform their own variable expansion,
+/// but a command substitution, arithmetic expansion, `~`, or parameter expansion has no dotenv
+/// equivalent at all and is rejected outright instead of being silently mangled.
+fn dotenv_value(raw_value: &str, name: &str) -> String {
+    let value_parts = match parser::parse_value(raw_value) {
+        Ok(value_parts) => value_parts,
+        Err(errors) => {
+            for error in errors {
+                parser::print_parse_error(
+                    error.level,
+                    raw_value,
+                    error.range,
+                    error.message,
+                    error.note.as_deref(),
+                );
+            }
+            exit(exitcode::DATAERR);
+        }
+    };
+
+    let mut rendered = String::with_capacity(raw_value.len() + 2);
+    rendered.push('"');
+    for part in &value_parts {
+        rendered.push_str(&render_dotenv_part(part, name));
+    }
+    rendered.push('"');
+    rendered
+}
+
+fn render_dotenv_part(part: &ValuePart, name: &str) -> String {
+    use ValuePartKind::*;
+
+    if part.expansion.is_some() {
+        error!(
+            "{}: parameter expansions like \"${{VAR:-replacement}}\" have no .env equivalent",
+            name,
+        );
+        exit(exitcode::DATAERR);
+    }
+
+    match part.kind {
+        Literal => part.value.replace('\\', r"\\").replace('"', "\\\"").replace('$', r"\$"),
+        ShellVariable => format!("${}", part.value),
+        ShellCommand | ShellArithmetic | Home => {
+            error!(
+                "{}: command substitutions, arithmetic, and \"~\" have no .env equivalent",
+                name,
+            );
+            exit(exitcode::DATAERR);
+        }
+    }
+}
+
+fn process_variable(
+    shell: &Shell,
+    name: &str,
+    raw_value: &EnvVariableValue,
+    resolve_paths: Option<PathResolutionMode>,
+    base_dir: &Path,
+    resolved_values: &mut HashMap<String, String>,
+) -> String {
     // If the value of the environment variable is `false`,
     // then add the "unset" script line to the String and skip the rest of this function.
     let script_line = match raw_value {
         EnvVariableValue::Set(false) => add_script_line::unset_variable(shell, name),
-        EnvVariableValue::Set(true) => add_script_line::set_variable(shell, name, "1", false),
+        EnvVariableValue::Set(true) => {
+            resolved_values.insert(name.to_string(), "1".to_string());
+            add_script_line::set_variable(shell, name, "1", false, true)
+        }
         EnvVariableValue::String(string) => {
-            let expanded_value = expand_value(string, shell);
-            add_script_line::set_variable(shell, name, &expanded_value, false)
+            let expanded_value =
+                expand_value(string, shell, resolve_paths, base_dir, resolved_values);
+            resolved_values.insert(name.to_string(), expanded_value.clone());
+            add_script_line::set_variable(shell, name, &expanded_value, false, true)
         }
         EnvVariableValue::Array(array_of_arrays) => {
             let flattened_array = array_of_arrays
                 .iter()
                 .flat_map(|array| array.iter())
-                .map(|value| expand_value(value, shell))
+                .map(|value| expand_value(value, shell, resolve_paths, base_dir, resolved_values))
                 .collect::<Vec<String>>()
                 .join(":");
-            add_script_line::set_variable(shell, name, &flattened_array, false)
+            add_script_line::set_variable(shell, name, &flattened_array, false, true)
         }
         EnvVariableValue::Path(path) => {
-            let path_string = path
+            let expanded_entries: Vec<String> = path
                 .iter()
-                .map(|value| expand_value(value, shell))
-                .collect::<Vec<String>>()
-                .join(":");
-            add_script_line::set_variable(shell, name, &path_string, true)
+                .map(|value| expand_value(value, shell, resolve_paths, base_dir, resolved_values))
+                .collect();
+            // Nushell has no colon-joined `PATH` string: it must be assigned as a list, with
+            // each entry its own `$"..."` expression (which `expand_value` already produced).
+            // Every other shell still joins its entries into the traditional `a:b:c` string.
+            let path_value = match shell {
+                Nushell => format!("[{}]", expanded_entries.join(", ")),
+                _ => expanded_entries.join(":"),
+            };
+            add_script_line::set_variable(shell, name, &path_value, true, true)
+        }
+        EnvVariableValue::PathModify(PathModify { prepend, append }) => {
+            let expand = |value: &String| {
+                expand_value(value, shell, resolve_paths, base_dir, resolved_values)
+            };
+            let prepend_entries: Vec<String> = prepend.iter().map(expand).collect();
+            let append_entries: Vec<String> = append.iter().map(expand).collect();
+            add_script_line::modify_path(shell, name, &prepend_entries, &append_entries)
+        }
+        EnvVariableValue::Default { value } => {
+            let replacement = match parser::parse_value(value) {
+                Ok(replacement) => replacement,
+                Err(errors) => {
+                    for error in errors {
+                        parser::print_parse_error(
+                            error.level,
+                            value,
+                            error.range,
+                            error.message,
+                            error.note.as_deref(),
+                        );
+                    }
+                    exit(exitcode::DATAERR);
+                }
+            };
+            let rendered = render_expansion(
+                name,
+                ExpansionOperator::AssignDefault,
+                &replacement,
+                shell,
+                resolve_paths,
+                base_dir,
+                resolved_values,
+            );
+            resolved_values.insert(name.to_string(), rendered.clone());
+            add_script_line::set_variable(shell, name, &rendered, false, true)
+        }
+        EnvVariableValue::Scoped { value: None, export } => {
+            add_script_line::set_export_attribute(shell, name, *export)
+        }
+        EnvVariableValue::Scoped { value: Some(value), export } => {
+            let expanded_value =
+                expand_value(value, shell, resolve_paths, base_dir, resolved_values);
+            resolved_values.insert(name.to_string(), expanded_value.clone());
+            add_script_line::set_variable(shell, name, &expanded_value, false, *export)
+        }
+        // Numbers and datetimes are already a safe, literal textual form straight from the TOML
+        // parser, with nothing to expand or quote - unlike `String`, they never flow through
+        // `expand_value`.
+        EnvVariableValue::Integer(integer) => {
+            let value = integer.to_string();
+            resolved_values.insert(name.to_string(), value.clone());
+            add_script_line::set_variable(shell, name, &value, false, true)
+        }
+        EnvVariableValue::Float(float) => {
+            let value = float.to_string();
+            resolved_values.insert(name.to_string(), value.clone());
+            add_script_line::set_variable(shell, name, &value, false, true)
+        }
+        EnvVariableValue::Datetime(datetime) => {
+            let value = datetime.to_string();
+            resolved_values.insert(name.to_string(), value.clone());
+            add_script_line::set_variable(shell, name, &value, false, true)
         }
     };
     script_line
@@ -83,23 +350,152 @@ fn process_variable(shell: &Shell, name: &str, raw_value: &EnvVariableValue) ->
 mod add_script_line {
     use crate::cli::Shell::{self, *};
 
-    pub fn set_variable(shell: &Shell, name: &str, value: &str, is_path: bool) -> String {
+    pub fn set_variable(
+        shell: &Shell,
+        name: &str,
+        value: &str,
+        is_path: bool,
+        export: bool,
+    ) -> String {
         // Log each processed variable
         if log_enabled!(log::Level::Trace) {
             let variable_log_header = match is_path {
                 true => "[Set]",
                 false => "'Set'",
             };
-            trace!("{}: {} -> {}", variable_log_header, name, value);
+            trace!("{}: {} -> {} (export={})", variable_log_header, name, value, export);
         };
 
         // Select the correct form for the chosen shell.
-        match shell {
-            Bash | Zsh => format!("export {}={}", name, value),
-            Fish => {
+        match (shell, export) {
+            (Bash | Zsh | Sh, true) => format!("export {}={}", name, value),
+            // Bash and Zsh shell variables are already local to the shell unless exported.
+            (Bash | Zsh | Sh, false) => format!("{}={}", name, value),
+            (Csh, true) => format!("setenv {} {}", name, value),
+            // csh/tcsh's `setenv` always exports; `set` is its non-exported shell-variable form.
+            (Csh, false) => format!("set {} = {}", name, value),
+            (Fish, true) => {
                 let path_option = if is_path { " --path" } else { "" };
                 format!("set -gx{} {} {}", path_option, name, value)
             }
+            (Fish, false) => {
+                let path_option = if is_path { " --path" } else { "" };
+                format!("set -g{} {} {}", path_option, name, value)
+            }
+            (Nushell, true) => format!("$env.{} = {}", name, value),
+            // A plain `let` binding stays local to the script and isn't placed into `$env`.
+            (Nushell, false) => format!("let {} = {}", name, value),
+            (PowerShell, true) => format!("$env:{} = {}", name, value),
+            // A bare `$name` assignment is a session variable, not an `Env:` drive entry.
+            (PowerShell, false) => format!("${} = {}", name, value),
+        }
+    }
+
+    /// Prepend and/or append entries to `name`'s existing value instead of replacing it outright,
+    /// used for `EnvVariableValue::PathModify`. `prepend`/`append` are already-rendered
+    /// self-quoting fragments (from `expand_value`), in the order they should sit nearest the
+    /// front/back of the existing value.
+    ///
+    /// Fish's `set` has native `--prepend`/`--append` flags that splice into the existing value
+    /// without needing to name-reference it, so those are used directly (as two separate
+    /// commands, since `set` only takes one of the two at a time); Nushell similarly has
+    /// `prepend`/`append` list filters for its own pipeline syntax. Every other shell builds the
+    /// new value by placing a quoted live reference to the variable's current value - the same
+    /// `"$NAME"`/`"$env:NAME"` form `render_part` renders for a `ShellVariable` - in between the
+    /// prepended and appended entries.
+    pub fn modify_path(shell: &Shell, name: &str, prepend: &[String], append: &[String]) -> String {
+        trace!("[PathModify]: {} -> prepend={:?}, append={:?}", name, prepend, append);
+
+        match shell {
+            Bash | Zsh | Sh => {
+                let value = join_around_reference(prepend, append, format!(r#""${}""#, name));
+                format!("export {}={}", name, value)
+            }
+            Csh => {
+                let value = join_around_reference(prepend, append, format!(r#""${}""#, name));
+                format!("setenv {} {}", name, value)
+            }
+            Fish => {
+                let mut lines = Vec::new();
+                if !prepend.is_empty() {
+                    lines.push(format!("set -gx --path --prepend {} {}", name, prepend.join(":")));
+                }
+                if !append.is_empty() {
+                    lines.push(format!("set -gx --path --append {} {}", name, append.join(":")));
+                }
+                lines.join("; ")
+            }
+            Nushell => {
+                let mut value = format!("$env.{}", name);
+                if !prepend.is_empty() {
+                    value = format!("({} | prepend [{}])", value, prepend.join(", "));
+                }
+                if !append.is_empty() {
+                    value = format!("({} | append [{}])", value, append.join(", "));
+                }
+                format!("$env.{} = {}", name, value)
+            }
+            PowerShell => {
+                let reference = format!(r#""$env:{}""#, name);
+                let value = join_around_reference(prepend, append, reference);
+                format!("$env:{} = {}", name, value)
+            }
+        }
+    }
+
+    /// Join `prepend` and `append` around `reference` (a rendered live reference to the
+    /// variable's existing value), the same `:`-joined form `process_variable` builds for a plain
+    /// `Array`/`Path` value. Shared by every shell whose `PathModify` form splices in an explicit
+    /// self-reference rather than using a native prepend/append primitive.
+    fn join_around_reference(prepend: &[String], append: &[String], reference: String) -> String {
+        let mut parts = prepend.to_vec();
+        parts.push(reference);
+        parts.extend(append.iter().cloned());
+        parts.join(":")
+    }
+
+    /// Set or clear the export attribute of `name` without touching its current value, used for
+    /// `EnvVariableValue::Scoped { value: None, .. }`.
+    pub fn set_export_attribute(shell: &Shell, name: &str, export: bool) -> String {
+        trace!("[Export]: {} -> export={}", name, export);
+
+        match (shell, export) {
+            (Bash | Zsh | Sh, true) => format!("export {}", name),
+            (Bash | Zsh, false) => format!("export -n {}", name),
+            // POSIX `sh` has no `export -n`: it isn't in the standard, and dash (a common
+            // `/bin/sh`) doesn't implement it. The value is carried through a temporary
+            // variable, unset, and reassigned unexported instead, the same idea as the
+            // read-back-and-redeclare tricks below.
+            (Sh, false) => {
+                format!("_xshe_tmp=${0}; unset {0}; {0}=$_xshe_tmp; unset _xshe_tmp", name)
+            }
+            // csh/tcsh has no way to change just the export attribute, so the value is read back
+            // out of whichever scope currently holds it and re-declared in the other one.
+            (Csh, true) => format!(r#"setenv {0} "${0}""#, name),
+            (Csh, false) => format!(r#"set {0} = "${0}"; unsetenv {0}"#, name),
+            (Fish, true) => format!("set -gx {0} ${0}", name),
+            (Fish, false) => format!("set -gu {0}", name),
+            // Nushell has no "unexport" primitive either: `hide-env` simply removes the name from
+            // `$env`, so the value is carried over into a plain `let` binding first.
+            (Nushell, true) => format!("$env.{0} = ${0}", name),
+            (Nushell, false) => format!("let {0} = $env.{0}; hide-env {0}", name),
+            (PowerShell, true) => format!("$env:{0} = ${0}", name),
+            (PowerShell, false) => format!(r"${0} = $env:{0}; Remove-Item Env:\{0}", name),
+        }
+    }
+
+    /// Define a command alias. The command body is quoted with `single_quote` (not expanded),
+    /// since it's meant to run fresh each time the alias is invoked, not once when the script is
+    /// sourced. PowerShell has no equivalent "alias to a command string" form, so it defines a
+    /// function wrapping the literal command source instead of quoting it as a string.
+    pub fn set_alias(shell: &Shell, name: &str, command: &str) -> String {
+        trace!("[Alias]: {} -> {}", name, command);
+
+        match shell {
+            Bash | Zsh | Sh => format!("alias {}={}", name, super::single_quote(command, shell)),
+            Csh | Fish => format!("alias {} {}", name, super::single_quote(command, shell)),
+            Nushell => format!("alias {} = {}", name, super::single_quote(command, shell)),
+            PowerShell => format!("function {} {{ {} }}", name, command),
         }
     }
 
@@ -108,62 +504,466 @@ mod add_script_line {
 
         // Select the correct form for the chosen shell.
         match shell {
-            Bash | Zsh => format!("unset {}", name),
+            Bash | Zsh | Sh => format!("unset {}", name),
+            Csh => format!("unsetenv {}", name),
             Fish => format!("set -ge {}", name),
+            Nushell => format!("hide-env {}", name),
+            PowerShell => format!(r"Remove-Item Env:\{}", name),
         }
     }
 }
 
-/// Given a `shell` and a `map` of all specific shell options, get the correct shell `EnvVariableValue`.
-/// Used by `to_shell_source` to filter the right `EnvVariableOption::Specific` for the current shell.
-fn value_for_specific<'a>(
-    shell: &Shell,
-    map: &'a IndexMap<String, EnvVariableValue>,
-) -> Option<&'a EnvVariableValue> {
+/// Given a `shell` and a `map` of all specific shell options, get the value for the current
+/// shell, falling back to the `_` catch-all if the shell has no entry of its own. Used to filter
+/// `AliasOption::Specific` (by `to_alias_source`) for the current shell; `EnvVariableOption`'s own
+/// `Specific` map has an OS layer on top of this same shell/`_` lookup, so it resolves itself via
+/// `resolve_specific` instead.
+fn value_for_specific<'a, V>(shell: &Shell, map: &'a IndexMap<String, V>) -> Option<&'a V> {
     let binding = shell.to_possible_value()?;
     let shell_name = binding.get_name();
     map.get(shell_name).or_else(|| map.get("_"))
 }
 
+/// Converts a `[alias]` table into `alias` script lines for the given `shell`, the same way
+/// `to_shell_source` converts `vars` into variable-assignment lines.
+///
+/// Unlike a variable's value, an alias's command body is never run through `expand_value`: it's
+/// meant to be evaluated lazily each time the alias is invoked, not expanded once when the script
+/// is sourced, so `$VAR`/`$(...)`/`~` survive into the generated script literally.
+pub(crate) fn to_alias_source(aliases: &IndexMap<String, AliasOption>, shell: &Shell) -> String {
+    let outputs: Vec<String> = aliases
+        .iter()
+        .filter_map(|(name, alias_option)| {
+            match alias_option {
+                AliasOption::General(command) => Some(command),
+                AliasOption::Specific(map) => value_for_specific(shell, map),
+            }
+            .map(|command| add_script_line::set_alias(shell, name, command))
+        })
+        .collect();
+    outputs.join("\n") + "\n"
+}
+
 /// Expand the literal representation of a string in the toml to a value that can be parsed by the
 /// given shell.
-fn expand_value(value: &str, shell: &Shell) -> String {
-    use ValuePartKind::*;
-
-    let value_parts = parser::parse_value(value);
+///
+/// `resolve_paths`, if set, eagerly resolves `~`/relative values against `base_dir` (see
+/// `path_resolution`) instead of emitting a dynamic lookup for the target shell to resolve.
+fn expand_value(
+    value: &str,
+    shell: &Shell,
+    resolve_paths: Option<PathResolutionMode>,
+    base_dir: &Path,
+    resolved_values: &HashMap<String, String>,
+) -> String {
+    let value_parts = match parser::parse_value(value) {
+        Ok(value_parts) => value_parts,
+        // The parser has no opinion on what happens once a value is unusable; report every
+        // fatal problem it found and abort here, since there's no shell script left to generate.
+        Err(errors) => {
+            for error in errors {
+                parser::print_parse_error(
+                    error.level,
+                    value,
+                    error.range,
+                    error.message,
+                    error.note.as_deref(),
+                );
+            }
+            exit(exitcode::DATAERR);
+        }
+    };
 
     // Pre-allocate space for the string
     let mut expanded_value = String::with_capacity(value.len() * 2);
 
-    // Handle each part for the specified shell, and concatenate each part together.
-    let shell_format = |kind: &ValuePartKind, value: &str| -> String {
-        match (kind, shell) {
-            (Literal, _) => single_quote(value, shell),
+    for part in &value_parts {
+        let rendered = render_part(part, shell, resolve_paths, base_dir, resolved_values);
+        expanded_value.push_str(&rendered);
+    }
+
+    // Every other shell renders each part as its own self-quoting fragment and relies on the
+    // shell's lexer to concatenate adjacent fragments into a single word. Nushell's parser
+    // doesn't do that, so for Nushell every part instead renders as a piece of a single
+    // `$"..."` interpolated string (a bare literal fragment, or a `(...)` sub-expression for
+    // anything dynamic), and the whole thing is wrapped here, once, at the end - unless the
+    // value is just one part that already rendered as a complete, standalone Nu expression
+    // (e.g. a `${VAR:-default}` expansion, or a `$((...))`/`$(...)`/`~` delegated to `^sh -c`),
+    // in which case wrapping it in `$"..."` too would be a spurious extra layer.
+    if matches!(shell, Nushell) {
+        let is_whole_value_an_expression = match value_parts.as_slice() {
+            [part] => is_standalone_nu_expression(part, resolve_paths, base_dir),
+            _ => false,
+        };
+        if !is_whole_value_an_expression {
+            expanded_value = format!(r#"$"{}""#, expanded_value);
+        }
+    }
+
+    expanded_value
+}
+
+/// Whether a lone `ValuePart` renders, for Nushell, as a complete expression in its own right
+/// (`(...)`) rather than a bare fragment meant to be spliced into an interpolated `$"..."`
+/// string - see `expand_value`'s use of this just above.
+fn is_standalone_nu_expression(
+    part: &ValuePart,
+    resolve_paths: Option<PathResolutionMode>,
+    base_dir: &Path,
+) -> bool {
+    use ValuePartKind::*;
+
+    if part.expansion.is_some() {
+        return true;
+    }
+
+    // Eager path resolution (see `resolve_part_path`) always lowers a `Home`/relative-literal
+    // part to a plain resolved string instead, which is a bare fragment even though `Home`
+    // would otherwise count below.
+    if let Some(mode) = resolve_paths {
+        if resolve_part_path(part, mode, base_dir).is_some() {
+            return false;
+        }
+    }
 
-            (ShellVariable, Bash | Zsh | Fish) => format!(r#""${}""#, value),
+    matches!(part.kind, ShellArithmetic | ShellCommand | Home)
+}
+
+// Render a single `ValuePart` as a standalone, self-quoting fragment of a shell script, suitable
+// for placing directly next to other rendered parts with no separator (see `expand_value`).
+fn render_part(
+    part: &ValuePart,
+    shell: &Shell,
+    resolve_paths: Option<PathResolutionMode>,
+    base_dir: &Path,
+    resolved_values: &HashMap<String, String>,
+) -> String {
+    use ValuePartKind::*;
+
+    if let Some(Expansion {
+        operator,
+        replacement,
+    }) = &part.expansion
+    {
+        return render_expansion(
+            &part.value,
+            *operator,
+            replacement,
+            shell,
+            resolve_paths,
+            base_dir,
+            resolved_values,
+        );
+    }
 
-            (ShellCommand, Bash | Zsh) => format!("$(eval {})", single_quote(value, shell)),
-            (ShellCommand, Fish) => format!("(eval {})", single_quote(value, shell)),
+    // If eager path resolution is enabled, a `Home` token or a literal relative path is lowered
+    // directly to the resolved absolute path instead of to a dynamic shell lookup.
+    if let Some(mode) = resolve_paths {
+        if let Some(resolved) = resolve_part_path(part, mode, base_dir) {
+            return match shell {
+                Nushell => escape_nu_interpolated(&resolved),
+                _ => single_quote(&resolved, shell),
+            };
+        }
+    }
 
-            (Home, Bash | Zsh) => {
-                format!("$(eval echo \"~{}\")", value)
+    // If `part` names a variable this same config already set earlier in the file, splice in
+    // its already-rendered value directly instead of a live shell lookup, so later variables see
+    // what this config itself computed rather than whatever happens to already be in the
+    // environment. A forward reference, a genuinely external variable, or a variable referencing
+    // itself (which can never appear here yet, since a name is only recorded after its own value
+    // is fully resolved) all fall through to the live lookup below unchanged.
+    //
+    // Nushell is excluded here: its rendered value is a whole `$"..."` expression, not a bare
+    // fragment meant to sit next to others with no separator, so it can't be spliced into the
+    // middle of another `$"..."` the way every other shell's self-quoting fragments can.
+    if !matches!(shell, Nushell) {
+        if let ValuePartKind::ShellVariable = part.kind {
+            if let Some(resolved) = resolved_values.get(part.value.as_ref()) {
+                return resolved.clone();
             }
-            (Home, Fish) => format!("(eval echo \"~{}\")", value),
         }
+    }
+
+    match (&part.kind, shell) {
+        (Literal, Nushell) => escape_nu_interpolated(&part.value),
+        (Literal, _) => single_quote(&part.value, shell),
+
+        (ShellVariable, Bash | Zsh | Sh | Csh | Fish) => format!(r#""${}""#, part.value),
+        (ShellVariable, Nushell) => format!("($env.{})", part.value),
+        (ShellVariable, PowerShell) => format!(r#""$env:{}""#, part.value),
+
+        (ShellCommand, Bash | Zsh | Sh) => format!("$(eval {})", single_quote(&part.value, shell)),
+        // csh/tcsh backticks run their contents directly (no `eval` needed), unlike the other
+        // shells here, which need it to turn a *value* back into something `$(...)`/`(...)` runs.
+        (ShellCommand, Csh) => format!("`{}`", part.value),
+        (ShellCommand, Fish) => format!("(eval {})", single_quote(&part.value, shell)),
+        // Nushell's `(...)` expects a nu expression, not arbitrary POSIX shell syntax, so (like
+        // csh/tcsh above) this delegates to `sh` too - just via `^sh -c` instead of backticks.
+        // Single-quoting (Bash's rules cover this fine) keeps this nested inside the `$"..."`
+        // string `expand_value` wraps the whole rendered value in, without unescaped `"`s.
+        (ShellCommand, Nushell) => format!("(^sh -c {})", single_quote(&part.value, &Bash)),
+        (ShellCommand, PowerShell) => {
+            format!("$(Invoke-Expression {})", single_quote(&part.value, shell))
+        }
+
+        (ShellArithmetic, Bash | Zsh | Sh) => format!("$(({}))", part.value),
+        // csh/tcsh has no arithmetic expansion syntax of its own, so delegate the expression to
+        // `sh`, which does.
+        (ShellArithmetic, Csh) => format!("`sh -c 'echo $(({}))'`", part.value),
+        (ShellArithmetic, Fish) => format!("(math {})", part.value),
+        // Same rationale as `ShellCommand` above: no native arithmetic syntax, so delegate to `sh`.
+        (ShellArithmetic, Nushell) => {
+            format!("(^sh -c {})", single_quote(&format!("echo $(({}))", part.value), &Bash))
+        }
+        (ShellArithmetic, PowerShell) => format!("$({})", part.value),
+
+        (Home, Bash | Zsh | Sh) => {
+            format!("$(eval echo \"~{}\")", part.value)
+        }
+        // Same rationale as `ShellArithmetic` above: delegate the tilde expansion to `sh`.
+        (Home, Csh) => format!("`sh -c 'echo ~{}'`", part.value),
+        (Home, Fish) => format!("(eval echo \"~{}\")", part.value),
+        (Home, Nushell) => {
+            format!("(^sh -c {})", single_quote(&format!("echo ~{}", part.value), &Bash))
+        }
+        (Home, PowerShell) => {
+            format!("$(Invoke-Expression \"echo ~{}\")", part.value)
+        }
+    }
+}
+
+/// Escape a literal fragment of text for use inside a Nushell `$"..."` interpolated string:
+/// backslashes, double quotes, and `(`, which would otherwise be misread as the start of a
+/// sub-expression.
+fn escape_nu_interpolated(text: &str) -> String {
+    text.replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('(', "\\(")
+}
+
+/// If `part` is a `Home` token or a literal relative path, resolve it against `base_dir`
+/// according to `mode` and return the result as a string.
+///
+/// Returns `None` for any other kind of part, or if resolution failed (eg. `canonicalize` on a
+/// path that doesn't exist), in which case the caller falls back to the normal dynamic lowering.
+fn resolve_part_path(
+    part: &ValuePart,
+    mode: PathResolutionMode,
+    base_dir: &Path,
+) -> Option<String> {
+    use ValuePartKind::*;
+
+    let resolved = match &part.kind {
+        Home => mode.resolve("", &path_resolution::resolve_home(&part.value)?),
+        Literal if part.value.starts_with("./") || part.value.starts_with("../") => {
+            mode.resolve(&part.value, base_dir)
+        }
+        _ => return None,
     };
 
-    for parser::ValuePart { value, kind } in value_parts {
-        expanded_value.push_str(&shell_format(&kind, &value));
+    match resolved {
+        Ok(path) => Some(path.to_string_lossy().into_owned()),
+        Err(e) => {
+            warn!("Could not resolve path {:?}: {}", part.value, e);
+            None
+        }
+    }
+}
+
+// Render a `${name:<op><replacement>}` parameter expansion for the given shell.
+//
+// For bash/zsh, the POSIX operator is native syntax, so it's emitted directly, with the
+// replacement rendered "unquoted" (see `render_part_unquoted`) since it already sits inside the
+// single pair of double quotes that wraps the whole expansion.
+//
+// Fish has no equivalent inline syntax, so it's translated to a `set -q`/`test`-based command
+// substitution that produces the same value.
+fn render_expansion(
+    name: &str,
+    operator: ExpansionOperator,
+    replacement: &[ValuePart],
+    shell: &Shell,
+    resolve_paths: Option<PathResolutionMode>,
+    base_dir: &Path,
+    resolved_values: &HashMap<String, String>,
+) -> String {
+    match shell {
+        Bash | Zsh | Sh => {
+            let op = match operator {
+                ExpansionOperator::UseDefault => ":-",
+                ExpansionOperator::AssignDefault => ":=",
+                ExpansionOperator::UseAlternate => ":+",
+                ExpansionOperator::ErrorIfUnset => ":?",
+            };
+            let replacement: String = replacement
+                .iter()
+                .map(|part| {
+                    render_part_unquoted(part, shell, resolve_paths, base_dir, resolved_values)
+                })
+                .collect();
+            format!(r#""${{{}{}{}}}""#, name, op, replacement)
+        }
+        // csh/tcsh has no equivalent operator either, and unlike `ShellCommand`/`ShellArithmetic`
+        // above, the whole expansion (not just one sub-expression) needs delegating to `sh` here,
+        // since the `:-`/`:=`/`:+`/`:?` syntax is POSIX shell's, not csh's.
+        Csh => {
+            let op = match operator {
+                ExpansionOperator::UseDefault => ":-",
+                ExpansionOperator::AssignDefault => ":=",
+                ExpansionOperator::UseAlternate => ":+",
+                ExpansionOperator::ErrorIfUnset => ":?",
+            };
+            let replacement: String = replacement
+                .iter()
+                .map(|part| {
+                    render_part_unquoted(part, &Bash, resolve_paths, base_dir, resolved_values)
+                })
+                .collect();
+            format!(r#"`sh -c 'echo "${{{}{}{}}}"'`"#, name, op, replacement)
+        }
+        Fish => {
+            let replacement: String = replacement
+                .iter()
+                .map(|part| {
+                    render_part_unquoted(part, shell, resolve_paths, base_dir, resolved_values)
+                })
+                .collect();
+            let is_set = format!(r#"set -q {0}; and test -n "${0}""#, name);
+            match operator {
+                ExpansionOperator::UseDefault | ExpansionOperator::AssignDefault => {
+                    format!(r#"({}; and echo "${}"; or echo {})"#, is_set, name, replacement)
+                }
+                ExpansionOperator::UseAlternate => {
+                    format!(r#"({}; and echo {}; or echo '')"#, is_set, replacement)
+                }
+                ExpansionOperator::ErrorIfUnset => {
+                    format!(
+                        r#"({}; and echo "${}"; or begin; echo {} >&2; exit 1; end)"#,
+                        is_set, name, replacement
+                    )
+                }
+            }
+        }
+        PowerShell => {
+            let replacement: String = replacement
+                .iter()
+                .map(|part| {
+                    render_part_unquoted(part, shell, resolve_paths, base_dir, resolved_values)
+                })
+                .collect();
+            let is_set = format!("(Test-Path Env:{0}) -and $env:{0}", name);
+            match operator {
+                ExpansionOperator::UseDefault | ExpansionOperator::AssignDefault => {
+                    format!("$(if ({}) {{ $env:{} }} else {{ {} }})", is_set, name, replacement)
+                }
+                ExpansionOperator::UseAlternate => {
+                    format!("$(if ({}) {{ {} }} else {{ '' }})", is_set, replacement)
+                }
+                ExpansionOperator::ErrorIfUnset => {
+                    format!(
+                        "$(if ({}) {{ $env:{} }} else {{ throw {} }})",
+                        is_set, name, replacement
+                    )
+                }
+            }
+        }
+        // Nushell's `if`/`else` are expressions, so (unlike the shells above) this doesn't need
+        // its own string-building hack. The replacement is rendered the same way `expand_value`
+        // renders a whole value for Nushell - each part as a fragment of one `$"..."` string -
+        // since that's exactly what it is: a nested value, substituted in as a single string.
+        Nushell => {
+            let replacement: String = replacement
+                .iter()
+                .map(|part| render_part(part, &Nushell, resolve_paths, base_dir, resolved_values))
+                .collect();
+            let replacement = format!(r#"$"{}""#, replacement);
+            let is_set = format!(r#"($env.{0}? != null and $env.{0} != "")"#, name);
+            match operator {
+                ExpansionOperator::UseDefault | ExpansionOperator::AssignDefault => {
+                    format!("(if {} {{ $env.{} }} else {{ {} }})", is_set, name, replacement)
+                }
+                ExpansionOperator::UseAlternate => {
+                    format!(r#"(if {} {{ {} }} else {{ "" }})"#, is_set, replacement)
+                }
+                ExpansionOperator::ErrorIfUnset => {
+                    format!(
+                        "(if {} {{ $env.{} }} else {{ error make {{msg: {}}} }})",
+                        is_set, name, replacement
+                    )
+                }
+            }
+        }
+    }
+}
+
+// Render a `ValuePart` the way it should appear inside a context that's already double-quoted
+// (bash/zsh) or otherwise doesn't need its own standalone quoting, such as the replacement word
+// of a `${VAR:-replacement}` parameter expansion. Nested variables keep their normal dynamic
+// form but lose their own quotes, since a second pair of literal `"` characters can't nest inside
+// the one already wrapping the whole expansion. Commands and tildes are rendered as usual since
+// they're wrapped in `$(...)`/`(...)`, which starts a fresh quoting context of its own.
+//
+// `resolved_values` is only forwarded here, not applied to the plain-variable arm itself: its
+// entries are standalone, self-quoting fragments (see `expand_value`) meant to sit in their own
+// quoting context, not spliced into one a caller already opened - so this always falls back to a
+// live lookup, and only passes the map down into `render_part` for the `ShellCommand`/
+// `ShellArithmetic`/`Home` arms, which do start a fresh context of their own.
+fn render_part_unquoted(
+    part: &ValuePart,
+    shell: &Shell,
+    resolve_paths: Option<PathResolutionMode>,
+    base_dir: &Path,
+    resolved_values: &HashMap<String, String>,
+) -> String {
+    use ValuePartKind::*;
+
+    if part.expansion.is_some() {
+        // A parameter expansion nested inside another one's replacement is a rare enough edge
+        // case that we fall back to the normal self-quoting form here.
+        return render_part(part, shell, resolve_paths, base_dir, resolved_values);
+    }
+
+    if resolve_paths.is_some() {
+        // An eagerly-resolved path is just a concrete string, so it can't collide with the
+        // surrounding quoting the way a dynamic `"$VAR"` lookup could; render it as usual.
+        let resolved = resolve_paths.and_then(|mode| resolve_part_path(part, mode, base_dir));
+        if let Some(resolved) = resolved {
+            return resolved
+                .replace('\\', r"\\")
+                .replace('"', "\\\"")
+                .replace('$', r"\$")
+                .replace('`', r"\`");
+        }
+    }
+
+    match (&part.kind, shell) {
+        // Nushell never reaches this branch in practice (its own `render_expansion` arm renders
+        // replacements via `render_part` instead, since it isn't sitting inside a string there),
+        // but the match still needs to stay exhaustive, so it falls back to the generic escaping.
+        (Literal, _) => part
+            .value
+            .replace('\\', r"\\")
+            .replace('"', "\\\"")
+            .replace('$', r"\$")
+            .replace('`', r"\`"),
+        (ShellVariable, Bash | Zsh | Sh | Csh | Fish) => format!("${}", part.value),
+        (ShellVariable, PowerShell) => format!("$env:{}", part.value),
+        (ShellVariable, Nushell) => format!("$env.{}", part.value),
+        (ShellCommand, _) | (ShellArithmetic, _) | (Home, _) => {
+            render_part(part, shell, resolve_paths, base_dir, resolved_values)
+        }
     }
-    expanded_value
 }
 
 // Surround a string in single quotes in a way that is best suited for a specific shell.
 // Specifically, Fish shell has a simpler way of escaping single quotes in a single quoted string,
-// while Bash and Zsh have to do it another way.
+// while Bash, Zsh, and csh/tcsh have to do it another way.
 fn single_quote(string: &str, shell: &Shell) -> String {
     match shell {
-        Bash | Zsh => string
+        // csh/tcsh can't escape a single quote within a single-quoted string either, so it needs
+        // the same close-quote/double-quote/reopen-quote trick as Bash and Zsh.
+        Bash | Zsh | Sh | Csh => string
             // Bash and Zsh can't escape any single quotes within a single-quoted string,
             // so whenever we encounter one we need to get the current string, begin a
             // double-quoted string containing the single quote, and then start a new
@@ -182,6 +982,126 @@ fn single_quote(string: &str, shell: &Shell) -> String {
             .collect::<Vec<String>>()
             .join(r#""'""#),
         Fish => format!("'{}'", string.replace('\\', "\\\\").replace('\'', "\\'")),
+        // PowerShell single-quoted strings escape a literal single quote by doubling it.
+        PowerShell => format!("'{}'", string.replace('\'', "''")),
+        // Value rendering never reaches here for Nushell: its own literal/path-resolution
+        // rendering escapes for the `$"..."` interpolated string it's embedded in (see
+        // `escape_nu_interpolated`) instead of calling this function. Nushell single-quoted
+        // strings can't escape an embedded single quote at all, so double quotes - which do
+        // support `\"` - are used here instead, for the one caller that does reach this arm:
+        // `add_script_line::set_alias`, which needs a literal, non-interpolated string.
+        Nushell => format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+/// Validate `file_data` without generating a script, as used by `--check`: every string value
+/// (in the base `vars`, every `[env.NAME]` overlay, and the deprecated `[shell.NAME]` tables) is
+/// checked for malformed interpolations the same way `to_shell_source` itself would parse them,
+/// and every per-shell key in a `EnvVariableOption::Specific` table or a `[shell.NAME]` table is
+/// checked against the shells xshe actually knows about.
+///
+/// Returns one human-readable problem description per issue found; an empty `Vec` means the
+/// config is clean. Collects every problem instead of stopping at the first one, so `--check` can
+/// report everything wrong with a config in one pass.
+pub(crate) fn check_config(file_data: &ConfigFile) -> Vec<String> {
+    let mut issues = Vec::new();
+
+    check_vars(&file_data.vars, "", &mut issues);
+
+    if let Some(env) = &file_data.env {
+        for (profile, profile_vars) in env {
+            check_vars(profile_vars, &format!("[env.{}] ", profile), &mut issues);
+        }
+    }
+
+    // Deprecated
+    if let Some(shell) = &file_data.shell {
+        for (shell_name, shell_vars) in shell {
+            if !is_known_shell(shell_name) {
+                issues.push(format!("[shell.{}] is not a shell xshe knows about", shell_name));
+            }
+            for (name, value) in shell_vars {
+                let context = format!("[shell.{}] {}", shell_name, name);
+                check_value(&context, value, &mut issues);
+            }
+        }
+    }
+
+    issues
+}
+
+fn check_vars(vars: &EnvironmentVariables, context: &str, issues: &mut Vec<String>) {
+    for (name, option) in vars {
+        match option {
+            EnvVariableOption::General(value) => {
+                check_value(&format!("{}{}", context, name), value, issues);
+            }
+            EnvVariableOption::Specific(map) => {
+                for (key, option) in map {
+                    if key != "_" && !is_known_shell(key) && !is_known_os(key) {
+                        issues.push(format!(
+                            "{}{}.{} is not a shell or OS xshe knows about",
+                            context, name, key
+                        ));
+                    }
+                    let key_context = format!("{}{}.{}", context, name, key);
+                    match option {
+                        OsOption::General(value) => check_value(&key_context, value, issues),
+                        OsOption::Specific(os_map) => {
+                            for (os_name, value) in os_map {
+                                if os_name != "_" && !is_known_os(os_name) {
+                                    issues.push(format!(
+                                        "{}.{} is not an OS xshe knows about",
+                                        key_context, os_name
+                                    ));
+                                }
+                                let os_context = format!("{}.{}", key_context, os_name);
+                                check_value(&os_context, value, issues);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether `name` is a canonical name or alias of a `Shell` variant (eg. "nu" or "nushell").
+fn is_known_shell(name: &str) -> bool {
+    Shell::value_variants()
+        .iter()
+        .filter_map(Shell::to_possible_value)
+        .any(|value| value.matches(name, false))
+}
+
+/// Whether `name` is a target-OS key xshe knows how to select on - one of the values
+/// `std::env::consts::OS` actually takes on the platforms xshe supports.
+fn is_known_os(name: &str) -> bool {
+    matches!(name, "linux" | "macos" | "windows")
+}
+
+fn check_value(context: &str, value: &EnvVariableValue, issues: &mut Vec<String>) {
+    let strings: Vec<&String> = match value {
+        EnvVariableValue::String(string) => vec![string],
+        EnvVariableValue::Array(arrays) => arrays.iter().flatten().collect(),
+        EnvVariableValue::Path(path) => path.iter().collect(),
+        EnvVariableValue::PathModify(PathModify { prepend, append }) => {
+            prepend.iter().chain(append.iter()).collect()
+        }
+        EnvVariableValue::Default { value } => vec![value],
+        EnvVariableValue::Scoped { value: Some(value), .. } => vec![value],
+        EnvVariableValue::Scoped { value: None, .. }
+        | EnvVariableValue::Set(_)
+        | EnvVariableValue::Integer(_)
+        | EnvVariableValue::Float(_)
+        | EnvVariableValue::Datetime(_) => return,
+    };
+    for string in strings {
+        if let Err(errors) = parser::parse_value(string) {
+            for error in errors {
+                issues.push(format!("{}: {}", context, error.message));
+            }
+        }
     }
 }
 
@@ -219,10 +1139,11 @@ mod test_conversion {
         );
 
         // Verify that the representation translates into the correct shell-script, for each shell.
+        let base_dir = std::path::Path::new("/home/superatomic");
         for (shell, shell_source) in shell_sources {
             assert_str_eq!(
                 // Trim the trailing newline(s), if they exist.
-                to_shell_source(&map, &shell).trim_end_matches('\n'),
+                to_shell_source(&map, &shell, None, base_dir).trim_end_matches('\n'),
                 shell_source.trim_end_matches('\n'),
                 "Check for correct {:?} syntax",
                 &shell,
@@ -242,7 +1163,11 @@ mod test_conversion {
             hashmap! {
                 Bash => r#"export FOO='Bar'"#,
                 Zsh => r#"export FOO='Bar'"#,
+                Sh => r#"export FOO='Bar'"#,
                 Fish => r#"set -gx FOO 'Bar'"#,
+                PowerShell => r#"$env:FOO = 'Bar'"#,
+                Csh => r#"setenv FOO 'Bar'"#,
+                Nushell => r#"$env.FOO = $"Bar""#,
             },
         )
     }
@@ -265,7 +1190,107 @@ mod test_conversion {
             hashmap! {
                 Bash => r#"export PATH='/usr/local/bin':'/usr/bin':'/bin':'/usr/sbin':'/sbin'"#,
                 Zsh => r#"export PATH='/usr/local/bin':'/usr/bin':'/bin':'/usr/sbin':'/sbin'"#,
+                Sh => r#"export PATH='/usr/local/bin':'/usr/bin':'/bin':'/usr/sbin':'/sbin'"#,
                 Fish => r#"set -gx --path PATH '/usr/local/bin':'/usr/bin':'/bin':'/usr/sbin':'/sbin'"#,
+                Nushell => concat!(
+                    r#"$env.PATH = [$"/usr/local/bin", $"/usr/bin", "#,
+                    r#"$"/bin", $"/usr/sbin", $"/sbin"]"#,
+                ),
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_path_modify_prepend() {
+        assert_convert(
+            // language=TOML
+            r#"PATH = { prepend = ["/usr/local/bin"] }"#,
+            indexmap! {
+                "PATH".into() => General(EnvVariableValue::PathModify(PathModify {
+                    prepend: vec!["/usr/local/bin".into()],
+                    append: vec![],
+                })),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export PATH='/usr/local/bin':"$PATH""#,
+                Zsh => r#"export PATH='/usr/local/bin':"$PATH""#,
+                Sh => r#"export PATH='/usr/local/bin':"$PATH""#,
+                Csh => r#"setenv PATH '/usr/local/bin':"$PATH""#,
+                Fish => r#"set -gx --path --prepend PATH '/usr/local/bin'"#,
+                PowerShell => r#"$env:PATH = '/usr/local/bin':"$env:PATH""#,
+                Nushell => r#"$env.PATH = ($env.PATH | prepend [$"/usr/local/bin"])"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_path_modify_append() {
+        assert_convert(
+            // language=TOML
+            r#"PATH = { append = ["/opt/bin"] }"#,
+            indexmap! {
+                "PATH".into() => General(EnvVariableValue::PathModify(PathModify {
+                    prepend: vec![],
+                    append: vec!["/opt/bin".into()],
+                })),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export PATH="$PATH":'/opt/bin'"#,
+                Fish => r#"set -gx --path --append PATH '/opt/bin'"#,
+                PowerShell => r#"$env:PATH = "$env:PATH":'/opt/bin'"#,
+                Nushell => r#"$env.PATH = ($env.PATH | append [$"/opt/bin"])"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_path_modify_both() {
+        // Both a `prepend` and an `append` list can be given together, either of which may have
+        // more than one entry; `Fish` emits one `set` command per direction, since its own
+        // `--prepend`/`--append` flags can't be combined in a single call.
+        assert_convert(
+            // language=TOML
+            r#"PATH = { prepend = ["/usr/local/bin", "$HOME/.local/bin"], append = ["/opt/bin"] }"#,
+            indexmap! {
+                "PATH".into() => General(EnvVariableValue::PathModify(PathModify {
+                    prepend: vec!["/usr/local/bin".into(), "$HOME/.local/bin".into()],
+                    append: vec!["/opt/bin".into()],
+                })),
+            },
+            // language=sh
+            hashmap! {
+                Bash => indoc! {r#"
+                    export PATH='/usr/local/bin':"$HOME"'/.local/bin':"$PATH":'/opt/bin'
+                "#},
+                Fish => indoc! {r#"
+                    set -gx --path --prepend PATH '/usr/local/bin':"$HOME"'/.local/bin'; set -gx --path --append PATH '/opt/bin'
+                "#},
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_default() {
+        // `Default` composes with the ordinary `${NAME:=value}` parameter expansion machinery
+        // (see `test_convert_expansion_assign_default`), so its rendered forms match what a
+        // literal `EDITOR = "${EDITOR:=nvim}"` string would already produce.
+        assert_convert(
+            // language=TOML
+            r#"EDITOR = { default = "nvim" }"#,
+            indexmap! {
+                "EDITOR".into() => General(EnvVariableValue::Default { value: "nvim".into() }),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export EDITOR="${EDITOR:=nvim}""#,
+                Zsh => r#"export EDITOR="${EDITOR:=nvim}""#,
+                Sh => r#"export EDITOR="${EDITOR:=nvim}""#,
+                Csh => r#"setenv EDITOR `sh -c 'echo "${EDITOR:=nvim}"'`"#,
+                Fish => r#"set -gx EDITOR (set -q EDITOR; and test -n "$EDITOR"; and echo "$EDITOR"; or echo nvim)"#,
+                PowerShell => r#"$env:EDITOR = $(if ((Test-Path Env:EDITOR) -and $env:EDITOR) { $env:EDITOR } else { nvim })"#,
+                Nushell => r#"$env.EDITOR = (if ($env.EDITOR? != null and $env.EDITOR != "") { $env.EDITOR } else { $"nvim" })"#,
             },
         )
     }
@@ -286,6 +1311,7 @@ mod test_conversion {
             hashmap! {
                 Bash => r#"export ARRAY='array_item_1':'array_item_2':'array_item_3'"#,
                 Zsh => r#"export ARRAY='array_item_1':'array_item_2':'array_item_3'"#,
+                Sh => r#"export ARRAY='array_item_1':'array_item_2':'array_item_3'"#,
                 Fish => r#"set -gx ARRAY 'array_item_1':'array_item_2':'array_item_3'"#,
             },
         )
@@ -303,7 +1329,10 @@ mod test_conversion {
             hashmap! {
                 Bash => r#"export HOMEBREW_NO_ANALYTICS=1"#,
                 Zsh => r#"export HOMEBREW_NO_ANALYTICS=1"#,
+                Sh => r#"export HOMEBREW_NO_ANALYTICS=1"#,
                 Fish => r#"set -gx HOMEBREW_NO_ANALYTICS 1"#,
+                Csh => r#"setenv HOMEBREW_NO_ANALYTICS 1"#,
+                Nushell => r#"$env.HOMEBREW_NO_ANALYTICS = 1"#,
             },
         )
     }
@@ -320,7 +1349,156 @@ mod test_conversion {
             hashmap! {
                 Bash => r#"unset HOMEBREW_NO_ANALYTICS"#,
                 Zsh => r#"unset HOMEBREW_NO_ANALYTICS"#,
+                Sh => r#"unset HOMEBREW_NO_ANALYTICS"#,
                 Fish => r#"set -ge HOMEBREW_NO_ANALYTICS"#,
+                PowerShell => r"Remove-Item Env:\HOMEBREW_NO_ANALYTICS",
+                Csh => r#"unsetenv HOMEBREW_NO_ANALYTICS"#,
+                Nushell => r#"hide-env HOMEBREW_NO_ANALYTICS"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_integer() {
+        assert_convert(
+            // language=TOML
+            "COUNT = 5",
+            indexmap! {
+                "COUNT".into() => General(EnvVariableValue::Integer(5)),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export COUNT=5"#,
+                Zsh => r#"export COUNT=5"#,
+                Sh => r#"export COUNT=5"#,
+                Fish => r#"set -gx COUNT 5"#,
+                PowerShell => r#"$env:COUNT = 5"#,
+                Csh => r#"setenv COUNT 5"#,
+                Nushell => r#"$env.COUNT = 5"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_float() {
+        assert_convert(
+            // language=TOML
+            "RATIO = 1.5",
+            indexmap! {
+                "RATIO".into() => General(EnvVariableValue::Float(1.5)),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export RATIO=1.5"#,
+                Zsh => r#"export RATIO=1.5"#,
+                Sh => r#"export RATIO=1.5"#,
+                Fish => r#"set -gx RATIO 1.5"#,
+                PowerShell => r#"$env:RATIO = 1.5"#,
+                Csh => r#"setenv RATIO 1.5"#,
+                Nushell => r#"$env.RATIO = 1.5"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_datetime() {
+        assert_convert(
+            // language=TOML
+            "BUILD_DATE = 2024-02-07T00:00:00Z",
+            indexmap! {
+                "BUILD_DATE".into() => General(EnvVariableValue::Datetime(
+                    "2024-02-07T00:00:00Z".parse().expect("valid RFC 3339 datetime"),
+                )),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export BUILD_DATE=2024-02-07T00:00:00Z"#,
+                Zsh => r#"export BUILD_DATE=2024-02-07T00:00:00Z"#,
+                Sh => r#"export BUILD_DATE=2024-02-07T00:00:00Z"#,
+                Fish => r#"set -gx BUILD_DATE 2024-02-07T00:00:00Z"#,
+                PowerShell => r#"$env:BUILD_DATE = 2024-02-07T00:00:00Z"#,
+                Csh => r#"setenv BUILD_DATE 2024-02-07T00:00:00Z"#,
+                Nushell => r#"$env.BUILD_DATE = 2024-02-07T00:00:00Z"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_boolean_not_misparsed_as_integer() {
+        // `Integer`/`Float` must not steal `true`/`false` away from `Set`: a bare TOML boolean
+        // has no integer or float representation, so there's no actual ambiguity, but this locks
+        // the behavior in as a regression test now that the untagged enum has more variants to
+        // try.
+        let true_data: ConfigFile = toml::from_str("FOO = true").expect("valid toml");
+        assert_eq!(true_data.vars["FOO"], General(EnvVariableValue::Set(true)));
+        let false_data: ConfigFile = toml::from_str("FOO = false").expect("valid toml");
+        assert_eq!(false_data.vars["FOO"], General(EnvVariableValue::Set(false)));
+    }
+
+    #[test]
+    fn test_convert_scoped_local() {
+        assert_convert(
+            // language=TOML
+            r#"HISTSIZE = { value = "1000", export = false }"#,
+            indexmap! {
+                "HISTSIZE".into() => General(EnvVariableValue::Scoped {
+                    value: Some("1000".into()),
+                    export: false,
+                }),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"HISTSIZE='1000'"#,
+                Zsh => r#"HISTSIZE='1000'"#,
+                Sh => r#"HISTSIZE='1000'"#,
+                Fish => r#"set -g HISTSIZE '1000'"#,
+                Csh => r#"set HISTSIZE = '1000'"#,
+                PowerShell => r#"$HISTSIZE = '1000'"#,
+                Nushell => r#"let HISTSIZE = $"1000""#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_scoped_unexport() {
+        assert_convert(
+            // language=TOML
+            "ALREADY_SET = { export = false }",
+            indexmap! {
+                "ALREADY_SET".into() => General(EnvVariableValue::Scoped {
+                    value: None,
+                    export: false,
+                }),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export -n ALREADY_SET"#,
+                Zsh => r#"export -n ALREADY_SET"#,
+                Fish => r#"set -gu ALREADY_SET"#,
+                Csh => r#"set ALREADY_SET = "$ALREADY_SET"; unsetenv ALREADY_SET"#,
+                PowerShell => r#"$ALREADY_SET = $env:ALREADY_SET; Remove-Item Env:\ALREADY_SET"#,
+                Nushell => r#"let ALREADY_SET = $env.ALREADY_SET; hide-env ALREADY_SET"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_sh_unexport() {
+        // POSIX `sh` has no `export -n`, unlike bash/zsh, so unexporting a value without losing
+        // it needs its own temp-variable dance instead of reusing the Bash/Zsh arm.
+        assert_convert(
+            // language=TOML
+            "ALREADY_SET = { export = false }",
+            indexmap! {
+                "ALREADY_SET".into() => General(EnvVariableValue::Scoped {
+                    value: None,
+                    export: false,
+                }),
+            },
+            // language=sh
+            hashmap! {
+                Sh => "_xshe_tmp=$ALREADY_SET; unset ALREADY_SET; \
+                    ALREADY_SET=$_xshe_tmp; unset _xshe_tmp",
             },
         )
     }
@@ -332,7 +1510,9 @@ mod test_conversion {
             r#"ONLY_FOR_BASH.bash = "Do people read test cases?""#,
             indexmap! {
                 "ONLY_FOR_BASH".into() => Specific(indexmap! {
-                    "bash".into() => EnvVariableValue::String("Do people read test cases?".into()),
+                    "bash".into() => OsOption::General(
+                        EnvVariableValue::String("Do people read test cases?".into())
+                    ),
                 }),
             },
             // language=sh
@@ -354,8 +1534,12 @@ mod test_conversion {
             "#},
             indexmap! {
                 "SOME_VARIABLE".into() => Specific(indexmap! {
-                    "fish".into() => EnvVariableValue::String("you're pretty".into()),
-                    "_".into() => EnvVariableValue::String("[ACCESS DENIED]".into()),
+                    "fish".into() => OsOption::General(
+                        EnvVariableValue::String("you're pretty".into())
+                    ),
+                    "_".into() => OsOption::General(
+                        EnvVariableValue::String("[ACCESS DENIED]".into())
+                    ),
                 })
             },
             // language=sh
@@ -381,11 +1565,15 @@ mod test_conversion {
             "#},
             indexmap! {
                 "SOME_VARIABLE".into() => Specific(indexmap! {
-                    "fish".into() => EnvVariableValue::String("you're pretty".into()),
-                    "_".into() => EnvVariableValue::String("[ACCESS DENIED]".into()),
+                    "fish".into() => OsOption::General(
+                        EnvVariableValue::String("you're pretty".into())
+                    ),
+                    "_".into() => OsOption::General(
+                        EnvVariableValue::String("[ACCESS DENIED]".into())
+                    ),
                 }),
                 "ANOTHER_VARIABLE".into() => Specific(indexmap! {
-                    "zsh".into() => EnvVariableValue::String("Zzz".into()),
+                    "zsh".into() => OsOption::General(EnvVariableValue::String("Zzz".into())),
                 }),
             },
             // language=sh
@@ -400,6 +1588,87 @@ mod test_conversion {
         )
     }
 
+    #[test]
+    fn test_resolve_specific_os_sibling_selector() {
+        // An OS key can sit directly alongside shell keys, specializing a variable by OS alone.
+        let os = std::env::consts::OS;
+        let vars = indexmap! {
+            "HOMEBREW_PREFIX".into() => Specific(indexmap! {
+                os.into() => OsOption::General(EnvVariableValue::String("running-os".into())),
+                "_".into() => OsOption::General(EnvVariableValue::String("fallback".into())),
+            }),
+        };
+        assert_eq!(
+            resolve_for_shell(&vars, &Bash),
+            indexmap! {
+                "HOMEBREW_PREFIX".into() => EnvVariableValue::String("running-os".into()),
+            },
+        );
+    }
+
+    #[test]
+    fn test_resolve_specific_os_sibling_falls_back_to_catch_all() {
+        // With no entry for the running OS, the `_` catch-all still applies, same as a plain
+        // shell-keyed `Specific` table.
+        let vars = indexmap! {
+            "HOMEBREW_PREFIX".into() => Specific(indexmap! {
+                "made-up-os".into() => OsOption::General(EnvVariableValue::String("other".into())),
+                "_".into() => OsOption::General(EnvVariableValue::String("fallback".into())),
+            }),
+        };
+        assert_eq!(
+            resolve_for_shell(&vars, &Bash),
+            indexmap! {
+                "HOMEBREW_PREFIX".into() => EnvVariableValue::String("fallback".into()),
+            },
+        );
+    }
+
+    #[test]
+    fn test_resolve_specific_shell_then_os_nested() {
+        // An OS table nested under a shell key specializes a variable for one particular shell,
+        // further split by OS - the running shell picks the shell key, then the OS table inside
+        // it picks (or falls back within) that narrower table.
+        let os = std::env::consts::OS;
+        let vars = indexmap! {
+            "HOMEBREW_PREFIX".into() => Specific(indexmap! {
+                "bash".into() => OsOption::Specific(indexmap! {
+                    os.into() => EnvVariableValue::String("bash-on-this-os".into()),
+                    "_".into() => EnvVariableValue::String("bash-fallback".into()),
+                }),
+            }),
+        };
+        assert_eq!(
+            resolve_for_shell(&vars, &Bash),
+            indexmap! {
+                "HOMEBREW_PREFIX".into() => EnvVariableValue::String("bash-on-this-os".into()),
+            },
+        );
+        // Fish has no entry at all here, so the variable is dropped entirely.
+        assert_eq!(resolve_for_shell(&vars, &Fish), IndexMap::new());
+    }
+
+    #[test]
+    fn test_resolve_specific_shell_match_wins_over_os_sibling() {
+        // A shell-specific entry is checked before an OS-sibling one, so the two can coexist
+        // without the OS key "stealing" a variable that already has a dedicated shell value.
+        let os = std::env::consts::OS;
+        let vars = indexmap! {
+            "HOMEBREW_PREFIX".into() => Specific(indexmap! {
+                "bash".into() => OsOption::General(
+                    EnvVariableValue::String("bash-specific".into())
+                ),
+                os.into() => OsOption::General(EnvVariableValue::String("os-specific".into())),
+            }),
+        };
+        assert_eq!(
+            resolve_for_shell(&vars, &Bash),
+            indexmap! {
+                "HOMEBREW_PREFIX".into() => EnvVariableValue::String("bash-specific".into()),
+            },
+        );
+    }
+
     #[test]
     fn test_shell_variables_inline() {
         assert_convert(
@@ -538,9 +1807,11 @@ mod test_conversion {
             indexmap! {
                 "FOO".into() => General(EnvVariableValue::String("bar".into())),
                 "BAZ".into() => Specific(indexmap! {
-                    "zsh".into() => EnvVariableValue::String("zž".into()),
-                    "fish".into() => EnvVariableValue::Path(vec!["gone".into(), "$fishing".into()]),
-                    "_".into() => EnvVariableValue::String("~other".into()),
+                    "zsh".into() => OsOption::General(EnvVariableValue::String("zž".into())),
+                    "fish".into() => OsOption::General(EnvVariableValue::Path(
+                        vec!["gone".into(), "$fishing".into()]
+                    )),
+                    "_".into() => OsOption::General(EnvVariableValue::String("~other".into())),
                 }),
                 "ARRAY_TEST".into() => General(EnvVariableValue::Array(vec![vec![
                     "$home".into(),
@@ -554,7 +1825,7 @@ mod test_conversion {
                 "THE_ECHO".into() => General(EnvVariableValue::String(r#"$(echo "\)")"#.into())),
                 "XSHE_IS_THE_BEST".into() => General(EnvVariableValue::Set(true)),
                 "XDG_CONFIG_HOME".into() => Specific(indexmap! {
-                    "bash".into() => EnvVariableValue::Set(false),
+                    "bash".into() => OsOption::General(EnvVariableValue::Set(false)),
                 }),
             },
             // language=sh
@@ -590,4 +1861,522 @@ mod test_conversion {
             },
         )
     }
+
+    #[test]
+    fn test_convert_cross_variable_interpolation() {
+        // A later variable referencing an earlier one by name should see the value this config
+        // itself just set, not fall back to a live `"$NAME"` shell lookup.
+        assert_convert(
+            // language=TOML
+            indoc! {r#"
+                XDG_DATA_HOME = "/data"
+                CARGO_HOME = "${XDG_DATA_HOME}/cargo"
+            "#},
+            indexmap! {
+                "XDG_DATA_HOME".into() => General(EnvVariableValue::String("/data".into())),
+                "CARGO_HOME".into() => General(EnvVariableValue::String(
+                    "${XDG_DATA_HOME}/cargo".into()
+                )),
+            },
+            // language=sh
+            hashmap! {
+                Bash => indoc! (r#"
+                    export XDG_DATA_HOME='/data'
+                    export CARGO_HOME='/data''/cargo'
+                "#),
+                Fish => indoc! (r#"
+                    set -gx XDG_DATA_HOME '/data'
+                    set -gx CARGO_HOME '/data''/cargo'
+                "#),
+                PowerShell => indoc! (r#"
+                    $env:XDG_DATA_HOME = '/data'
+                    $env:CARGO_HOME = '/data''/cargo'
+                "#),
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_cross_variable_interpolation_ignores_nushell() {
+        // Nushell renders each value as a whole `$"..."` expression rather than a bare
+        // self-quoting fragment, so it keeps the live `($env.NAME)` lookup instead.
+        assert_convert(
+            // language=TOML
+            indoc! {r#"
+                XDG_DATA_HOME = "/data"
+                CARGO_HOME = "${XDG_DATA_HOME}/cargo"
+            "#},
+            indexmap! {
+                "XDG_DATA_HOME".into() => General(EnvVariableValue::String("/data".into())),
+                "CARGO_HOME".into() => General(EnvVariableValue::String(
+                    "${XDG_DATA_HOME}/cargo".into()
+                )),
+            },
+            // language=sh
+            hashmap! {
+                Nushell => indoc! (r#"
+                    $env.XDG_DATA_HOME = $"/data"
+                    $env.CARGO_HOME = $"($env.XDG_DATA_HOME)/cargo"
+                "#),
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_self_reference_falls_back_to_live_lookup() {
+        // A variable referencing its own name can never find itself in the resolved-values table
+        // yet (it's only inserted after its own value is fully computed), so it keeps the normal
+        // live lookup - the same behavior as referencing a variable this config never set at all.
+        assert_convert(
+            // language=TOML
+            r#"PATH = "$PATH:/opt/bin""#,
+            indexmap! {
+                "PATH".into() => General(EnvVariableValue::String("$PATH:/opt/bin".into())),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export PATH="$PATH"':/opt/bin'"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_expansion_use_default() {
+        assert_convert(
+            // language=TOML
+            r#"XDG_DATA_HOME = "${XDG_DATA_HOME:-$HOME/.local/share}""#,
+            indexmap! {
+                "XDG_DATA_HOME".into() => General(EnvVariableValue::String(
+                    "${XDG_DATA_HOME:-$HOME/.local/share}".into(),
+                )),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export XDG_DATA_HOME="${XDG_DATA_HOME:-$HOME/.local/share}""#,
+                Zsh => r#"export XDG_DATA_HOME="${XDG_DATA_HOME:-$HOME/.local/share}""#,
+                Fish => r#"set -gx XDG_DATA_HOME (set -q XDG_DATA_HOME; and test -n "$XDG_DATA_HOME"; and echo "$XDG_DATA_HOME"; or echo $HOME/.local/share)"#,
+                Csh => r#"setenv XDG_DATA_HOME `sh -c 'echo "${XDG_DATA_HOME:-$HOME/.local/share}"'`"#,
+                Nushell => r#"$env.XDG_DATA_HOME = (if ($env.XDG_DATA_HOME? != null and $env.XDG_DATA_HOME != "") { $env.XDG_DATA_HOME } else { $"($env.HOME)/.local/share" })"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_nested_command_substitution() {
+        assert_convert(
+            // language=TOML
+            r#"ARCH_MESSAGE = "$(echo $(uname -m))""#,
+            indexmap! {
+                "ARCH_MESSAGE".into() => General(EnvVariableValue::String(
+                    "$(echo $(uname -m))".into(),
+                )),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export ARCH_MESSAGE=$(eval 'echo $(uname -m)')"#,
+                Zsh => r#"export ARCH_MESSAGE=$(eval 'echo $(uname -m)')"#,
+                Fish => r#"set -gx ARCH_MESSAGE (eval 'echo $(uname -m)')"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_arithmetic() {
+        assert_convert(
+            // language=TOML
+            r#"THREADS = "$((NPROC * 2))""#,
+            indexmap! {
+                "THREADS".into() => General(EnvVariableValue::String("$((NPROC * 2))".into())),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export THREADS=$((NPROC * 2))"#,
+                Zsh => r#"export THREADS=$((NPROC * 2))"#,
+                Fish => r#"set -gx THREADS (math NPROC * 2)"#,
+                PowerShell => r#"$env:THREADS = $(NPROC * 2)"#,
+                Csh => r#"setenv THREADS `sh -c 'echo $((NPROC * 2))'`"#,
+                Nushell => r#"$env.THREADS = (^sh -c 'echo $((NPROC * 2))')"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_expansion_use_alternate() {
+        assert_convert(
+            // language=TOML
+            r#"VERBOSE_FLAG = "${DEBUG:+--verbose}""#,
+            indexmap! {
+                "VERBOSE_FLAG".into() => General(EnvVariableValue::String("${DEBUG:+--verbose}".into())),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export VERBOSE_FLAG="${DEBUG:+--verbose}""#,
+                Zsh => r#"export VERBOSE_FLAG="${DEBUG:+--verbose}""#,
+                Fish => r#"set -gx VERBOSE_FLAG (set -q DEBUG; and test -n "$DEBUG"; and echo --verbose; or echo '')"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_expansion_assign_default() {
+        assert_convert(
+            // language=TOML
+            r#"XDG_CACHE_HOME = "${XDG_CACHE_HOME:=$HOME/.cache}""#,
+            indexmap! {
+                "XDG_CACHE_HOME".into() => General(EnvVariableValue::String(
+                    "${XDG_CACHE_HOME:=$HOME/.cache}".into(),
+                )),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export XDG_CACHE_HOME="${XDG_CACHE_HOME:=$HOME/.cache}""#,
+                Zsh => r#"export XDG_CACHE_HOME="${XDG_CACHE_HOME:=$HOME/.cache}""#,
+                Fish => r#"set -gx XDG_CACHE_HOME (set -q XDG_CACHE_HOME; and test -n "$XDG_CACHE_HOME"; and echo "$XDG_CACHE_HOME"; or echo $HOME/.cache)"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_expansion_error_if_unset() {
+        assert_convert(
+            // language=TOML
+            r#"API_KEY = "${API_KEY:?API_KEY must be set}""#,
+            indexmap! {
+                "API_KEY".into() => General(EnvVariableValue::String(
+                    "${API_KEY:?API_KEY must be set}".into(),
+                )),
+            },
+            // language=sh
+            hashmap! {
+                Bash => r#"export API_KEY="${API_KEY:?API_KEY must be set}""#,
+                Zsh => r#"export API_KEY="${API_KEY:?API_KEY must be set}""#,
+                Fish => r#"set -gx API_KEY (set -q API_KEY; and test -n "$API_KEY"; and echo "$API_KEY"; or begin; echo API_KEY must be set >&2; exit 1; end)"#,
+            },
+        )
+    }
+
+    #[test]
+    fn test_convert_resolve_paths_absolutize_relative() {
+        let base_dir = std::path::Path::new("/home/superatomic/bin");
+        let vars = indexmap! {
+            "RELATIVE".into() => General(EnvVariableValue::String("../share".into())),
+        };
+        assert_str_eq!(
+            to_shell_source(&vars, &Bash, Some(PathResolutionMode::Absolutize), base_dir),
+            "export RELATIVE='/home/superatomic/share'\n",
+        );
+    }
+
+    #[test]
+    fn test_convert_resolve_paths_absolutize_tilde() {
+        let home = dirs::home_dir().expect("test environment should have a home directory");
+        let vars = indexmap! {
+            "TILDE".into() => General(EnvVariableValue::String("~".into())),
+        };
+        let expected = format!("export TILDE='{}'\n", home.display());
+        assert_str_eq!(
+            to_shell_source(
+                &vars,
+                &Bash,
+                Some(PathResolutionMode::Absolutize),
+                std::path::Path::new("/home/superatomic/bin"),
+            ),
+            expected,
+        );
+    }
+
+    #[test]
+    fn test_convert_alias_general() {
+        // language=TOML
+        let toml_str = indoc! {r#"
+            [alias]
+            ll = "ls -la"
+        "#};
+        let file_data: ConfigFile = toml::from_str(toml_str).expect("valid toml");
+        assert_eq!(
+            file_data.alias,
+            Some(indexmap! {
+                "ll".into() => AliasOption::General("ls -la".into()),
+            }),
+        );
+
+        let aliases = file_data.alias.expect("alias table should be present");
+        let bash_source = to_alias_source(&aliases, &Bash);
+        assert_str_eq!(bash_source.trim_end_matches('\n'), "alias ll='ls -la'");
+        let zsh_source = to_alias_source(&aliases, &Zsh);
+        assert_str_eq!(zsh_source.trim_end_matches('\n'), "alias ll='ls -la'");
+        let fish_source = to_alias_source(&aliases, &Fish);
+        assert_str_eq!(fish_source.trim_end_matches('\n'), "alias ll 'ls -la'");
+        let csh_source = to_alias_source(&aliases, &Csh);
+        assert_str_eq!(csh_source.trim_end_matches('\n'), "alias ll 'ls -la'");
+        assert_str_eq!(
+            to_alias_source(&aliases, &Nushell).trim_end_matches('\n'),
+            r#"alias ll = "ls -la""#,
+        );
+        assert_str_eq!(
+            to_alias_source(&aliases, &PowerShell).trim_end_matches('\n'),
+            "function ll { ls -la }",
+        );
+    }
+
+    #[test]
+    fn test_convert_alias_specific() {
+        // language=TOML
+        let toml_str = indoc! {r#"
+            [alias]
+            ll.fish = "ls -la --color"
+            ll._ = "ls -la"
+        "#};
+        let file_data: ConfigFile = toml::from_str(toml_str).expect("valid toml");
+        assert_eq!(
+            file_data.alias,
+            Some(indexmap! {
+                "ll".into() => AliasOption::Specific(indexmap! {
+                    "fish".into() => "ls -la --color".to_string(),
+                    "_".into() => "ls -la".to_string(),
+                }),
+            }),
+        );
+
+        let aliases = file_data.alias.expect("alias table should be present");
+        assert_str_eq!(
+            to_alias_source(&aliases, &Fish).trim_end_matches('\n'),
+            "alias ll 'ls -la --color'",
+        );
+        let bash_source = to_alias_source(&aliases, &Bash);
+        assert_str_eq!(bash_source.trim_end_matches('\n'), "alias ll='ls -la'");
+    }
+
+    #[test]
+    fn test_convert_alias_not_expanded() {
+        // Alias bodies are evaluated lazily each time the alias is invoked, so `$HOME` and `~`
+        // must survive into the script literally instead of being expanded like a variable value.
+        // language=TOML
+        let toml_str = indoc! {r#"
+            [alias]
+            gohome = "cd $HOME && ls ~"
+        "#};
+        let file_data: ConfigFile = toml::from_str(toml_str).expect("valid toml");
+        let aliases = file_data.alias.expect("alias table should be present");
+        assert_str_eq!(
+            to_alias_source(&aliases, &Bash).trim_end_matches('\n'),
+            r#"alias gohome='cd $HOME && ls ~'"#,
+        );
+    }
+
+    #[test]
+    fn test_convert_dotenv_basic() {
+        let vars = indexmap! {
+            "FOO".into() => General(EnvVariableValue::String("bar".into())),
+            "PATH".into() =>
+                General(EnvVariableValue::Path(vec!["/bin".into(), "/usr/bin".into()])),
+            "ARRAY".into() => General(EnvVariableValue::Array(vec![vec!["a".into(), "b".into()]])),
+            "HOMEBREW_NO_ANALYTICS".into() => General(EnvVariableValue::Set(true)),
+            "XDG_CONFIG_HOME".into() => General(EnvVariableValue::Set(false)),
+        };
+        assert_str_eq!(
+            to_dotenv_source(&vars).trim_end_matches('\n'),
+            indoc! {r#"
+                FOO="bar"
+                PATH="/bin":"/usr/bin"
+                ARRAY="a":"b"
+                HOMEBREW_NO_ANALYTICS="1"
+            "#}
+            .trim_end_matches('\n'),
+        );
+    }
+
+    #[test]
+    fn test_convert_dotenv_shell_variable_verbatim() {
+        let vars = indexmap! {
+            "GREETING".into() => General(EnvVariableValue::String("hello $NAME".into())),
+        };
+        assert_str_eq!(
+            to_dotenv_source(&vars).trim_end_matches('\n'),
+            r#"GREETING="hello $NAME""#,
+        );
+    }
+
+    #[test]
+    fn test_convert_dotenv_path_modify() {
+        // Dotenv has no shell to run `set --prepend`/`export` in, so a `PathModify` value falls
+        // back to the same verbatim `$NAME` self-reference dotenv already uses for a plain
+        // `$VAR` in a string value.
+        let vars = indexmap! {
+            "PATH".into() => General(EnvVariableValue::PathModify(PathModify {
+                prepend: vec!["/usr/local/bin".into()],
+                append: vec!["/opt/bin".into()],
+            })),
+        };
+        assert_str_eq!(
+            to_dotenv_source(&vars).trim_end_matches('\n'),
+            r#"PATH="/usr/local/bin":$PATH:"/opt/bin""#,
+        );
+    }
+
+    #[test]
+    fn test_convert_dotenv_default() {
+        // A dotenv loader already leaves an existing key untouched by default, so `Default`
+        // needs no guard of its own here - it's just the plain literal value.
+        let vars = indexmap! {
+            "EDITOR".into() => General(EnvVariableValue::Default { value: "nvim".into() }),
+        };
+        assert_str_eq!(to_dotenv_source(&vars).trim_end_matches('\n'), r#"EDITOR="nvim""#);
+    }
+
+    #[test]
+    fn test_convert_dotenv_specific_uses_catch_all_only() {
+        let vars = indexmap! {
+            "ONLY_FOR_BASH".into() => Specific(indexmap! {
+                "bash".into() => OsOption::General(EnvVariableValue::String("bash-only".into())),
+            }),
+            "SOME_VARIABLE".into() => Specific(indexmap! {
+                "fish".into() => OsOption::General(EnvVariableValue::String("fish-only".into())),
+                "_".into() => OsOption::General(EnvVariableValue::String("default".into())),
+            }),
+        };
+        let dotenv_source = to_dotenv_source(&vars);
+        assert_str_eq!(dotenv_source.trim_end_matches('\n'), r#"SOME_VARIABLE="default""#);
+    }
+
+    #[test]
+    fn test_resolve_for_shell_narrows_specific_and_drops_undefined() {
+        let vars = indexmap! {
+            "GENERAL".into() => General(EnvVariableValue::String("same-everywhere".into())),
+            "PER_SHELL".into() => Specific(indexmap! {
+                "bash".into() => OsOption::General(EnvVariableValue::String("bash-value".into())),
+                "fish".into() => OsOption::General(EnvVariableValue::String("fish-value".into())),
+            }),
+        };
+        assert_eq!(
+            resolve_for_shell(&vars, &Bash),
+            indexmap! {
+                "GENERAL".into() => EnvVariableValue::String("same-everywhere".into()),
+                "PER_SHELL".into() => EnvVariableValue::String("bash-value".into()),
+            },
+        );
+        // Nushell gets neither a `nu` nor a `_` entry for `PER_SHELL`, so it's dropped entirely.
+        assert_eq!(
+            resolve_for_shell(&vars, &Nushell),
+            indexmap! {
+                "GENERAL".into() => EnvVariableValue::String("same-everywhere".into()),
+            },
+        );
+    }
+
+    #[test]
+    fn test_dump_round_trips_through_json_and_toml() {
+        let vars = indexmap! {
+            "FOO".into() => General(EnvVariableValue::String("bar".into())),
+            "HISTSIZE".into() => General(EnvVariableValue::Scoped {
+                value: Some("1000".into()),
+                export: false,
+            }),
+        };
+        let resolved = resolve_for_shell(&vars, &Bash);
+
+        let json = serde_json::to_string(&resolved).expect("resolved map serializes to JSON");
+        let from_json: IndexMap<String, EnvVariableValue> =
+            serde_json::from_str(&json).expect("dumped JSON deserializes back");
+        assert_eq!(from_json, resolved);
+
+        let toml = toml::to_string(&resolved).expect("resolved map serializes to TOML");
+        let from_toml: IndexMap<String, EnvVariableValue> =
+            toml::from_str(&toml).expect("dumped TOML deserializes back");
+        assert_eq!(from_toml, resolved);
+    }
+
+    #[test]
+    fn test_resolve_profile_overlay() {
+        // language=TOML
+        let toml_str = indoc! {r#"
+            FOO = "base"
+            BAR = "only-in-base"
+
+            [env.prod]
+            FOO = "prod-value"
+        "#};
+        let file_data: ConfigFile = toml::from_str(toml_str).expect("valid toml");
+        let resolved = file_data
+            .resolve_profile(Some("prod"))
+            .expect("prod is a known profile");
+        assert_eq!(
+            resolved,
+            indexmap! {
+                "FOO".into() => General(EnvVariableValue::String("prod-value".into())),
+                "BAR".into() => General(EnvVariableValue::String("only-in-base".into())),
+            },
+        );
+    }
+
+    #[test]
+    fn test_resolve_profile_none_returns_base() {
+        // language=TOML
+        let toml_str = indoc! {r#"
+            FOO = "base"
+
+            [env.prod]
+            FOO = "prod-value"
+        "#};
+        let file_data: ConfigFile = toml::from_str(toml_str).expect("valid toml");
+        let resolved = file_data.resolve_profile(None).expect("no profile requested");
+        assert_eq!(
+            resolved,
+            indexmap! {
+                "FOO".into() => General(EnvVariableValue::String("base".into())),
+            },
+        );
+    }
+
+    #[test]
+    fn test_resolve_profile_unknown_errors() {
+        // language=TOML
+        let toml_str = indoc! {r#"
+            FOO = "base"
+
+            [env.prod]
+            FOO = "prod-value"
+        "#};
+        let file_data: ConfigFile = toml::from_str(toml_str).expect("valid toml");
+        assert!(file_data.resolve_profile(Some("staging")).is_err());
+    }
+
+    #[test]
+    fn test_merge_from_overwrites_per_variable_and_per_profile() {
+        // language=TOML
+        let base_str = indoc! {r#"
+            FOO = "base-foo"
+            BAR = "only-in-base"
+
+            [env.prod]
+            FOO = "base-prod-foo"
+        "#};
+        // language=TOML
+        let override_str = indoc! {r#"
+            FOO = "override-foo"
+            BAZ = "only-in-override"
+
+            [env.prod]
+            QUUX = "override-prod-quux"
+        "#};
+        let mut base: ConfigFile = toml::from_str(base_str).expect("valid toml");
+        let overlay: ConfigFile = toml::from_str(override_str).expect("valid toml");
+        base.merge_from(overlay);
+
+        assert_eq!(
+            base.vars,
+            indexmap! {
+                "FOO".into() => General(EnvVariableValue::String("override-foo".into())),
+                "BAR".into() => General(EnvVariableValue::String("only-in-base".into())),
+                "BAZ".into() => General(EnvVariableValue::String("only-in-override".into())),
+            },
+        );
+        assert_eq!(
+            base.env.expect("prod profile should still be present")["prod"],
+            indexmap! {
+                "FOO".into() => General(EnvVariableValue::String("base-prod-foo".into())),
+                "QUUX".into() => General(EnvVariableValue::String("override-prod-quux".into())),
+            },
+        );
+    }
 }
@@ -13,54 +13,148 @@
 
 //! Parse a value of a configuration into a vector of parts.
 
-use std::ops::RangeInclusive;
-use std::process::exit;
+use std::borrow::Cow;
+use std::ops::{Range, RangeInclusive};
 
 /// Represents part of the value of a shell script.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ValuePartKind {
-    Literal,       // A literal value.
-    ShellVariable, // A shell variable. Represented in the toml file as $val or ${val}
-    ShellCommand,  // A shell command. Represented in the toml file as $(command)
-    Home,          // A home directory. Represented as ~ normally, but sometimes as ~name
+    Literal,         // A literal value.
+    ShellVariable,   // A shell variable. Represented in the toml file as $val or ${val}
+    ShellCommand,    // A shell command. Represented in the toml file as $(command)
+    ShellArithmetic, // An arithmetic expansion. Represented in the toml file as $((expression))
+    Home,            // A home directory. Represented as ~ normally, but sometimes as ~name
 }
 
 /// A part of a environment variable value with a specific function,
 /// as determined by its kind (ValuePartKind).
+///
+/// `value` borrows directly from the `raw_value` that was parsed whenever possible (the common
+/// case: a contiguous run of characters with no escapes), and only owns a freshly built `String`
+/// for parts that had to be rewritten, such as ones containing backslash escapes.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct ValuePart {
+pub struct ValuePart<'a> {
     pub kind: ValuePartKind, // The function of this specific part.
-    pub value: String,       // The contents of the part.
+    pub value: Cow<'a, str>, // The contents of the part.
+
+    /// Set when a `ShellVariable` was written with a POSIX parameter expansion operator, eg.
+    /// `${XDG_DATA_HOME:-$HOME/.local/share}`. Always `None` for every other kind of part.
+    pub expansion: Option<Expansion<'a>>,
 }
 
-impl ValuePart {
-    fn new(kind: ValuePartKind) -> Self {
+/// The operator used in a `${VAR:<op><replacement>}` parameter expansion.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExpansionOperator {
+    UseDefault,    // ${VAR:-replacement}: use `replacement` if VAR is unset or empty.
+    AssignDefault, // ${VAR:=replacement}: also assigns `replacement` to VAR if unset or empty.
+    UseAlternate,  // ${VAR:+replacement}: use `replacement` only if VAR is set and non-empty.
+    ErrorIfUnset,  // ${VAR:?replacement}: error out with `replacement` as the message if unset.
+}
+
+/// The `<replacement>` half of a `${VAR:<op><replacement>}` parameter expansion, parsed
+/// recursively so that the replacement can itself contain variables, commands, or `~`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Expansion<'a> {
+    pub operator: ExpansionOperator,
+    pub replacement: Vec<ValuePart<'a>>,
+}
+
+/// Builds up a `ValuePart` while scanning `source`, borrowing a slice of it for as long as
+/// possible and only falling back to an owned `String` once a character has to be added that
+/// isn't a verbatim copy of `source` at the expected position (e.g. a backslash escape).
+struct PartBuilder<'a> {
+    kind: ValuePartKind,
+    source: &'a str,
+    start: usize,
+    end: usize,
+    owned: Option<String>,
+    expansion: Option<Expansion<'a>>,
+}
+
+impl<'a> PartBuilder<'a> {
+    fn new(source: &'a str, kind: ValuePartKind, start: usize) -> Self {
         Self {
             kind,
-            value: String::new(),
+            source,
+            start,
+            end: start,
+            owned: None,
+            expansion: None,
         }
     }
 
-    /// Add a char to the value.
-    fn push(&mut self, char: char) {
-        self.value.push(char);
+    /// Add a char to the value that is a verbatim copy of `source[at..]`.
+    /// Stays borrowed for as long as `at` is contiguous with what's already been added.
+    fn push(&mut self, char: char, at: usize) {
+        match &mut self.owned {
+            Some(owned) => owned.push(char),
+            None if at == self.end => self.end += char.len_utf8(),
+            None => {
+                // Not contiguous with the borrowed slice (e.g. recovering after an escape):
+                // fall back to an owned copy of what's been collected so far, plus this char.
+                let mut owned = self.source[self.start..self.end].to_string();
+                owned.push(char);
+                self.owned = Some(owned);
+            }
+        }
+    }
+
+    /// Add a char that was synthesized by the parser (e.g. reconstructing an escape sequence)
+    /// rather than copied verbatim from `source`. Always forces an owned value.
+    fn push_synthetic(&mut self, char: char) {
+        let owned = self
+            .owned
+            .get_or_insert_with(|| self.source[self.start..self.end].to_string());
+        owned.push(char);
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.owned {
+            Some(owned) => owned.is_empty(),
+            None => self.start == self.end,
+        }
+    }
+
+    fn into_value_part(self) -> ValuePart<'a> {
+        let value = match self.owned {
+            Some(owned) => Cow::Owned(owned),
+            None => Cow::Borrowed(&self.source[self.start..self.end]),
+        };
+        // "ndots" path segments (eg. `.../share`, nu-path's term for it) only make sense as a
+        // literal path fragment, so only expand them on `Literal` parts.
+        let value = match self.kind {
+            ValuePartKind::Literal => match expand_ndots(&value) {
+                Some(expanded) => Cow::Owned(expanded),
+                None => value,
+            },
+            _ => value,
+        };
+        ValuePart {
+            kind: self.kind,
+            value,
+            expansion: self.expansion,
+        }
     }
 
     // Add self to a vector (res).
-    fn push_self_to(self, res: &mut Vec<Self>) {
+    fn push_self_to(self, res: &mut Vec<ValuePart<'a>>) {
         // Display the current section if logging is trace level.
-        trace!("{:?}", self);
+        trace!("{:?} {:?}", self.kind, self.owned.as_deref().unwrap_or(&self.source[self.start..self.end]));
         // Don't add ValueParts with nothing added to them.
         // The exception is `ValuePartKind::Home`, which can be valid with no additional value.
-        if !self.value.is_empty() || self.kind == ValuePartKind::Home {
-            res.push(self);
+        let is_empty = self.is_empty();
+        let kind = self.kind;
+        if !is_empty || kind == ValuePartKind::Home {
+            res.push(self.into_value_part());
         }
     }
 
-    /// Add self to a vector (res), and then return a new ValuePart with the specified kind.
-    fn push_self_and_new(self, res: &mut Vec<Self>, kind: ValuePartKind) -> Self {
+    /// Add self to a vector (res), and then return a new PartBuilder with the specified kind,
+    /// starting right after the end of the part that was just pushed.
+    fn push_self_and_new(self, res: &mut Vec<ValuePart<'a>>, kind: ValuePartKind, at: usize) -> Self {
+        let source = self.source;
         self.push_self_to(res);
-        Self::new(kind)
+        Self::new(source, kind, at)
     }
 }
 
@@ -73,11 +167,42 @@ enum ValueParsingState {
     BeginShellStatement, // The previous characters was a dollar sign ($).
 }
 
+/// A single problem found while parsing a value, queued up instead of aborting immediately.
+///
+/// `fatal` diagnostics mean the parsed result shouldn't be trusted; `parse_value` still finishes
+/// the pass (recovering into a `Literal` where it can) so that every problem in the value can be
+/// reported at once, instead of only the first one encountered.
+struct Diagnostic {
+    level: log::Level,
+    range: RangeInclusive<usize>,
+    message: &'static str,
+    /// An optional "did you mean ..." follow-up, eg. suggesting the ASCII character a Unicode
+    /// confusable was most likely meant to be.
+    note: Option<String>,
+    fatal: bool,
+}
+
+/// A fatal problem found while parsing a value, returned to the caller instead of exiting
+/// from within the parser.
+///
+/// `parse_value` itself has no opinion on what should happen once parsing fails; it's up to the
+/// caller to render these (with `print_parse_error`) and decide whether/how to abort.
+#[derive(Debug)]
+pub struct ParseError {
+    pub level: log::Level,
+    pub range: RangeInclusive<usize>,
+    pub message: &'static str,
+    pub note: Option<String>,
+}
+
 /// Parses a value line in the configuration into a vector of `ValuePart`s.
 /// This allows for the different parts of a value to be converted into a usable form for any shell.
 ///
-/// May exit the cli app early if a non-recoverable parse occurs.
-pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
+/// Collects every problem found along the way. Non-fatal ones are reported immediately, since
+/// they don't change whether the result can be trusted. If any fatal problem was found, the
+/// parsed result is discarded and every fatal `ParseError` is returned instead, leaving the
+/// decision of whether (and how) to abort to the caller.
+pub fn parse_value(raw_value: &str) -> Result<Vec<ValuePart>, Vec<ParseError>> {
     use ValueParsingState::*;
     use ValuePartKind::*;
 
@@ -87,12 +212,19 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
     // vector. Because most shells expect some value, it is important to return at least one
     // element, even if the element itself has an empty value.
     if raw_value.is_empty() {
-        return Vec::from([ValuePart::new(Literal)]);
+        return Ok(Vec::from([ValuePart {
+            kind: Literal,
+            value: Cow::Borrowed(""),
+            expansion: None,
+        }]));
     }
 
     // Result buffer. This is the vector that is returned if the `raw_value` is not empty.
     let mut res = Vec::new();
 
+    // Every problem found while parsing this value, reported together once parsing finishes.
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
     // Parsing state for giving context for how to handle different characters.
     let mut parsing_mode = Normal;
 
@@ -118,17 +250,34 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
 
     // The current part that is being added.
     // Defaults to `ValuePartKind::Literal`, even if something else immediately changes it.
-    let mut current_part = ValuePart::new(Literal);
+    let mut current_part = PartBuilder::new(raw_value, Literal, 0);
 
-    // The index that we started a part at.
+    // The byte index that we started a part at.
     // Used only for displaying error messages and nothing else.
     let mut shell_statement_starting_index = 0;
 
+    // A parameter expansion operator (eg. `${VAR:-default}`) is scanned for its matching `}` all
+    // at once, ahead of the main per-character loop below. This is the first byte index after
+    // that scan that the loop should resume normal per-character handling at.
+    let mut skip_until = 0;
+
+    // How many unclosed `(` a `ShellCommand` part has seen since its own opening one. A nested
+    // command substitution (eg. `$(echo $(uname -m))`) is never parsed out into its own part -
+    // its text is just carried verbatim inside the outer `ShellCommand`, since the target shell
+    // re-evaluates it when the whole thing is handed to `eval` - but the scan still needs to
+    // count nested parens so it doesn't mistake an inner `)` for the outer command's own closer.
+    let mut shell_command_paren_depth: u32 = 0;
+
     // Loop through each character and react accordingly.
     //
-    // This code enumerates through the index so it can be used to display error messages.
-    // Besides showing error messages, the index is not used.
-    for (index, symbol) in raw_value.chars().enumerate() {
+    // This iterates by byte index (not character count) so that a `ValuePart` can always be
+    // sliced directly out of `raw_value`, even when it contains multi-byte characters.
+    for (index, symbol) in raw_value.char_indices() {
+        // Skip over characters already consumed by a parameter expansion operator scan.
+        if index < skip_until {
+            continue;
+        }
+
         // If the parser continues a part until it reaches a non-valid character to end the part on,
         // as we do for non-bracketed shell variables (eg. $VAR) and home tildes (eg. ~username),
         // we need to end the state *before* we match it, so we don't skip the character that ended
@@ -162,7 +311,7 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
             // In other words, if it's actually a `Literal`, it'll work,
             // and if it's not it'll automatically be changed because that's the normal behavior
             // of the following match statement anyway.
-            current_part = current_part.push_self_and_new(&mut res, Literal);
+            current_part = current_part.push_self_and_new(&mut res, Literal, index);
             parse_until_shell_separator = false; // Make sure that we've ended the state!
         }
 
@@ -175,15 +324,16 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
 
             // Backslash escapes are not allowed inside of shell variables.
             //
-            // Display an error message stating the problem and exit.
+            // Record a fatal diagnostic, but recover by simply dropping the backslash and
+            // continuing to parse the rest of the variable.
             (Normal, '\\', ShellVariable) => {
-                print_parse_error(
-                    log::Level::Error,
-                    raw_value,
-                    index..=index,
-                    "Fatal: Shell variables cannot contain backslashes",
-                );
-                exit(exitcode::DATAERR);
+                diagnostics.push(Diagnostic {
+                    level: log::Level::Error,
+                    range: index..=index,
+                    message: "Fatal: Shell variables cannot contain backslashes",
+                    note: None,
+                    fatal: true,
+                });
             }
 
             // A backslash in normal conditions begins a backslash escape sequence
@@ -206,9 +356,10 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
 
             // If there is a tilde at the start of the string, we switch to a home tilde mode.
             // Otherwise, the tilde character behaves normally.
-            (Normal, '~', Literal) if res.is_empty() && current_part.value.is_empty() => {
+            (Normal, '~', Literal) if res.is_empty() && current_part.is_empty() => {
                 // End the current part (which is guaranteed to be empty) and begin a new `Home` one
-                current_part = ValuePart::new(Home);
+                // that starts right after the tilde, since the tilde itself isn't part of the value.
+                current_part = PartBuilder::new(raw_value, Home, index + '~'.len_utf8());
 
                 // There is no character that changes the mode from `ValuePartKind::Home` back to
                 // `ValuePartKind::Literal`. Instead, it changes back once a non-valid character for
@@ -218,6 +369,20 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
                 parse_until_shell_separator = true;
             }
 
+            // An opening paren nested inside a `ShellCommand` means its matching closing paren
+            // (scanned for below) isn't the one that ends the outer command.
+            (Normal, '(', ShellCommand) => {
+                shell_command_paren_depth += 1;
+                current_part.push(symbol, index);
+            }
+
+            // A closing paren that only balances a nested one: bring the depth back down and
+            // keep scanning the same `ShellCommand`, instead of ending it.
+            (Normal, ')', ShellCommand) if shell_command_paren_depth > 0 => {
+                shell_command_paren_depth -= 1;
+                current_part.push(symbol, index);
+            }
+
             // Note that this is the union of two different match conditions!
             //
             // Matches an ending bracket/parenthesis, and returns the mode to `Literal`.
@@ -230,7 +395,61 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
             (Normal, ')', ShellCommand) | (Normal, '}', ShellVariable)
                 if !parse_until_shell_separator =>
             {
-                current_part = current_part.push_self_and_new(&mut res, Literal);
+                current_part = current_part.push_self_and_new(&mut res, Literal, index + 1);
+            }
+
+            // A ':' directly after a variable name inside braces may begin a POSIX parameter
+            // expansion operator, eg. `${XDG_DATA_HOME:-$HOME/.local/share}`. Only bracketed
+            // shell variables support these operators, so this doesn't apply while
+            // `parse_until_shell_separator` is set (a non-bracketed variable like `$VAR`).
+            (Normal, ':', ShellVariable) if !parse_until_shell_separator => {
+                match scan_expansion_operator(raw_value, index) {
+                    Some(scanned) => {
+                        let replacement =
+                            match parse_value(&raw_value[scanned.replacement_range.clone()]) {
+                                Ok(replacement) => replacement,
+                                Err(errors) => {
+                                    // Shift each nested error's range to be relative to the whole
+                                    // value, and fold them into this parse's own diagnostics.
+                                    let shift = scanned.replacement_range.start;
+                                    for error in errors {
+                                        diagnostics.push(Diagnostic {
+                                            level: error.level,
+                                            range: (error.range.start() + shift)
+                                                ..=(error.range.end() + shift),
+                                            message: error.message,
+                                            note: error.note,
+                                            fatal: true,
+                                        });
+                                    }
+                                    Vec::new()
+                                }
+                            };
+                        current_part.expansion = Some(Expansion {
+                            operator: scanned.operator,
+                            replacement,
+                        });
+                        current_part = current_part.push_self_and_new(
+                            &mut res,
+                            Literal,
+                            scanned.close_index + 1,
+                        );
+                        skip_until = scanned.close_index + 1;
+                    }
+                    // Not a recognized operator (or never closed): handled the same as any other
+                    // invalid character in a bracketed shell variable, below.
+                    None => {
+                        diagnostics.push(Diagnostic {
+                            level: log::Level::Error,
+                            range: index..=index,
+                            message: "Fatal: Shell variables cannot contain this character",
+                            note: None,
+                            fatal: true,
+                        });
+                        current_part.kind = Literal;
+                        current_part.push_synthetic(':');
+                    }
+                }
             }
 
             // Make sure to disallow character that are not allowed as shell variables.
@@ -252,20 +471,25 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
             // like `${VAR}`), as opposed to non-bracketed shell variables, which will simply end
             // their part.
             //
-            // Display an error message stating the problem and exit.
+            // Record a fatal diagnostic, but recover by downgrading this part to a `Literal` and
+            // continuing to parse the rest of the value as such. If the character is a Unicode
+            // confusable (eg. a fullwidth `＿` pasted in place of `_`), suggest the ASCII fix.
             (Normal, char, ShellVariable) if !is_valid_shell_variable(char) => {
-                print_parse_error(
-                    log::Level::Error,
-                    raw_value,
-                    index..=index,
-                    "Fatal: Shell variables cannot contain this character",
-                );
-                exit(exitcode::DATAERR);
+                diagnostics.push(Diagnostic {
+                    level: log::Level::Error,
+                    range: index..=index,
+                    message: "Fatal: Shell variables cannot contain this character",
+                    note: confusable_suggestion(char)
+                        .map(|ascii| format!("Did you mean '{}' instead of '{}'?", ascii, char)),
+                    fatal: true,
+                });
+                current_part.kind = Literal;
+                current_part.push_synthetic(char);
             }
 
             // Otherwise, if none of the special conditions above were met in the `Normal` state,
             // just append the character to the `current_part`.
-            (Normal, char, _) => current_part.push(char),
+            (Normal, char, _) => current_part.push(char, index),
 
             // ========================== //
             // OTHER VALUE PARSING STATES //
@@ -278,30 +502,30 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
                 match (part, char) {
                     // If it is a valid escape sequence, add its literal value.
                     (_, '\\') | (Literal, '$') | (ShellCommand, '(' | ')') => {
-                        current_part.push(char)
+                        current_part.push_synthetic(char)
                     }
 
-                    // Otherwise, display an error message, and add the literal value along with the
+                    // Otherwise, record a diagnostic, and add the literal value along with the
                     // preceding backslash.
                     _ => {
                         // Be more specific about why the character cannot be escaped, if possible.
-                        let problem = if "$()".contains(char) {
+                        let message = if "$()".contains(char) {
                             "You don't need to escape this value here"
                         } else {
                             "Not a valid escape character"
                         };
 
-                        // Display an error message explaining that the backslash was not valid.
-                        print_parse_error(
-                            log::Level::Warn,
-                            raw_value,
-                            (index - 1)..=index,
-                            problem,
-                        );
+                        diagnostics.push(Diagnostic {
+                            level: log::Level::Warn,
+                            range: (index - 1)..=index,
+                            message,
+                            note: None,
+                            fatal: false,
+                        });
 
                         // Add the literal value.
-                        current_part.push('\\');
-                        current_part.push(char);
+                        current_part.push_synthetic('\\');
+                        current_part.push_synthetic(char);
                     }
                 }
                 // Return to normal parsing since the backslash-escaped character has been escaped.
@@ -311,20 +535,53 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
             // Handle what happens when a non-escaped '$' character was the previous character.
             (BeginShellStatement, char, Literal) => {
                 match char {
+                    // Arithmetic expansion. $((expression))
+                    //
+                    // Checked before the plain shell-command case below, since `$((` and `$(`
+                    // share the same first character and only diverge on the second one.
+                    '(' if raw_value[index + '('.len_utf8()..].starts_with('(') => {
+                        let expression_start = index + 2 * '('.len_utf8();
+                        // End the literal part that came before the "$((", same as the plain
+                        // shell-command and shell-variable cases do.
+                        current_part = current_part.push_self_and_new(
+                            &mut res,
+                            ShellArithmetic,
+                            expression_start,
+                        );
+                        match scan_arithmetic_expression(raw_value, expression_start) {
+                            Some(scanned) => {
+                                current_part.end = scanned.expression_range.end;
+                                current_part = current_part.push_self_and_new(
+                                    &mut res,
+                                    Literal,
+                                    scanned.close_index + 1,
+                                );
+                                skip_until = scanned.close_index + 1;
+                            }
+                            // Never closed: leave `current_part` as `ShellArithmetic` so the
+                            // end-of-input check below reports the same fatal "unclosed shell
+                            // statement" error as an unclosed `$(` or `${` does.
+                            None => {}
+                        }
+                    }
+
                     // Shell Command. $(...)
                     '(' => {
-                        current_part = current_part.push_self_and_new(&mut res, ShellCommand);
+                        current_part =
+                            current_part.push_self_and_new(&mut res, ShellCommand, index + 1);
                     }
 
                     // Bracket wrapped Shell Variable ${...}
                     '{' => {
-                        current_part = current_part.push_self_and_new(&mut res, ShellVariable);
+                        current_part =
+                            current_part.push_self_and_new(&mut res, ShellVariable, index + 1);
                     }
 
                     // Non-wrapped Shell Variable $...
                     char if is_valid_shell_variable(char) => {
-                        current_part = current_part.push_self_and_new(&mut res, ShellVariable);
-                        current_part.push(char);
+                        current_part =
+                            current_part.push_self_and_new(&mut res, ShellVariable, index);
+                        current_part.push(char, index);
                         // Automatically end this part once a character that is not valid in a shell
                         // variable occurs.
                         parse_until_shell_separator = true;
@@ -332,16 +589,17 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
 
                     // Invalid subsequent character after the '$'.
                     _ => {
-                        print_parse_error(
-                            log::Level::Warn,
-                            raw_value,
-                            (index - 1)..=index,
-                            "Inline shell variables cannot begin with this character",
-                        );
+                        diagnostics.push(Diagnostic {
+                            level: log::Level::Warn,
+                            range: (index - 1)..=index,
+                            message: "Inline shell variables cannot begin with this character",
+                            note: None,
+                            fatal: false,
+                        });
 
                         // Add the literal value instead of starting a new part.
-                        current_part.push('$');
-                        current_part.push(char);
+                        current_part.push_synthetic('$');
+                        current_part.push_synthetic(char);
                     }
                 }
 
@@ -359,39 +617,37 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
     //
     // This function will exit early if `raw_value` is empty, so this has no risk of panicking.
     let end_index = (raw_value.len() - 1)..=(raw_value.len() - 1);
-    // Display an error message if an unescaped backslash or dollar-sign was the final character.
-    // If it was, the program doesn't have to exit. Instead, it will just add the literal value and
-    // send a warning message to fix the problem immediately.
-    //
-    // In either case, when an invalid ending does occur, an error message will be displayed, and
-    // the problematic character will be treated as if it was escaped.
+    // Record a diagnostic if an unescaped backslash or dollar-sign was the final character.
+    // Neither case is fatal by itself; the problematic character is just treated as if escaped.
     match parsing_mode {
         // `raw_value` ended with an unescaped backslash
         Backslash => {
-            print_parse_error(
-                log::Level::Warn,
-                raw_value,
-                end_index,
-                "Backslash was not escaped",
-            );
-            current_part.push('\\');
+            diagnostics.push(Diagnostic {
+                level: log::Level::Warn,
+                range: end_index,
+                message: "Backslash was not escaped",
+                note: None,
+                fatal: false,
+            });
+            current_part.push_synthetic('\\');
         }
         // `raw_value` ended with an unescaped dollar sign
         BeginShellStatement => {
-            print_parse_error(
-                log::Level::Warn,
-                raw_value,
-                end_index,
-                "Unused final \"$\". Use \"\\$\" instead",
-            );
-            current_part.push('$');
+            diagnostics.push(Diagnostic {
+                level: log::Level::Warn,
+                range: end_index,
+                message: "Unused final \"$\". Use \"\\$\" instead",
+                note: None,
+                fatal: false,
+            });
+            current_part.push_synthetic('$');
         }
 
         // Otherwise, it's fine.
         Normal => {}
     }
 
-    // Display an error message if a shell statement that must be closed is not closed.
+    // Record a fatal diagnostic if a shell statement that must be closed is not closed.
     // For example, these statements will cause the following error message to occur:
     //
     //   $(which micro
@@ -400,23 +656,51 @@ pub fn parse_value(raw_value: &str) -> Vec<ValuePart> {
     // To fix these, all that would be needed would be to add the closing ) or } to the end.
     // However, syntax like $PATH or ~user is still okay, so we only display this error if the
     // current_part.kind isn't a Literal AND if we aren't in the `parse_until_shell_separator` mode.
+    //
+    // There is no easy way to recover the intended meaning of an unclosed statement, so it's
+    // downgraded to a `Literal` of its raw (unclosed) contents before being pushed.
     if current_part.kind != Literal && !parse_until_shell_separator {
-        print_parse_error(
-            log::Level::Error,
-            raw_value,
-            shell_statement_starting_index..=(raw_value.len() - 1),
-            "Fatal: Unclosed shell statement!",
-        );
-
-        // There is no easy way to recover from this problem, so exit the app with a non-zero exit
-        // code after displaying an error message.
-        exit(exitcode::DATAERR);
+        diagnostics.push(Diagnostic {
+            level: log::Level::Error,
+            range: shell_statement_starting_index..=(raw_value.len() - 1),
+            message: "Fatal: Unclosed shell statement!",
+            note: None,
+            fatal: true,
+        });
+        current_part.kind = Literal;
     }
 
     // Add the final part to the return value
     current_part.push_self_to(&mut res);
 
-    res
+    // Non-fatal diagnostics don't affect whether the result can be trusted, so report them
+    // immediately. Fatal ones are handed back to the caller instead of reported here, since this
+    // function has no opinion on what should happen once parsing fails.
+    let mut fatal_errors = Vec::new();
+    for diagnostic in diagnostics {
+        if diagnostic.fatal {
+            fatal_errors.push(ParseError {
+                level: diagnostic.level,
+                range: diagnostic.range,
+                message: diagnostic.message,
+                note: diagnostic.note,
+            });
+        } else {
+            print_parse_error(
+                diagnostic.level,
+                raw_value,
+                diagnostic.range,
+                diagnostic.message,
+                diagnostic.note.as_deref(),
+            );
+        }
+    }
+
+    if !fatal_errors.is_empty() {
+        return Err(fatal_errors);
+    }
+
+    Ok(res)
 }
 
 /// A function that determines what characters are allowed inside of a shell variable.
@@ -424,15 +708,157 @@ fn is_valid_shell_variable(char: char) -> bool {
     char.is_alphanumeric() || char == '_'
 }
 
+/// Expand "ndots" path segments, as seen in nushell's `nu-path` crate: a whole path segment (ie.
+/// bounded by `/`, start-of-string, or end-of-string) made up of three or more `.` characters
+/// expands to `N - 1` `..` parent references, so `...` becomes `../..` and `....` becomes
+/// `../../..`. Exactly one or two dots (`.` and `..`) are left untouched, as is a dot-run that
+/// isn't a whole segment by itself (eg. the `...` in `foo...bar`).
+///
+/// Returns `None` if `literal` contains no such segment, so the caller can keep the original
+/// borrowed value instead of needlessly allocating.
+fn expand_ndots(literal: &str) -> Option<String> {
+    if !literal
+        .split('/')
+        .any(|segment| segment.len() >= 3 && segment.bytes().all(|byte| byte == b'.'))
+    {
+        return None;
+    }
+
+    let mut expanded = String::with_capacity(literal.len());
+    for (index, segment) in literal.split('/').enumerate() {
+        if index > 0 {
+            expanded.push('/');
+        }
+        if segment.len() >= 3 && segment.bytes().all(|byte| byte == b'.') {
+            let parent_refs = segment.len() - 1;
+            for parent_ref in 0..parent_refs {
+                if parent_ref > 0 {
+                    expanded.push('/');
+                }
+                expanded.push_str("..");
+            }
+        } else {
+            expanded.push_str(segment);
+        }
+    }
+    Some(expanded)
+}
+
+/// If `char` is a Unicode "confusable" that's easy to paste in by accident in place of an ASCII
+/// character (eg. a fullwidth `＿` from an IME, or a curly quote from a word processor), return
+/// the ASCII character it was most likely meant to be.
+fn confusable_suggestion(char: char) -> Option<char> {
+    // The fullwidth form block mirrors printable ASCII (U+0021..=U+007E) at a fixed offset.
+    const FULLWIDTH_OFFSET: u32 = 0xFF01 - 0x21;
+    if ('\u{FF01}'..='\u{FF5E}').contains(&char) {
+        return char::from_u32(char as u32 - FULLWIDTH_OFFSET);
+    }
+
+    Some(match char {
+        '\u{2018}' | '\u{2019}' => '\'',
+        '\u{201C}' | '\u{201D}' => '"',
+        _ => return None,
+    })
+}
+
+/// The result of scanning a `${VAR:<op><replacement>}` parameter expansion, starting right after
+/// a variable name has been consumed inside braces.
+struct ScannedExpansion {
+    operator: ExpansionOperator,
+    replacement_range: Range<usize>,
+    close_index: usize,
+}
+
+/// Given the byte index of a ':' found directly after a variable name inside braces, determine
+/// whether it begins a recognized parameter expansion operator, and if so, scan ahead to find the
+/// `}` that closes it (respecting any braces nested inside the replacement).
+///
+/// Returns `None` if the character after the ':' isn't a recognized operator, or if the
+/// expansion is never closed.
+fn scan_expansion_operator(raw_value: &str, colon_index: usize) -> Option<ScannedExpansion> {
+    let after_colon = colon_index + ':'.len_utf8();
+    let operator_char = raw_value[after_colon..].chars().next()?;
+    let operator = match operator_char {
+        '-' => ExpansionOperator::UseDefault,
+        '=' => ExpansionOperator::AssignDefault,
+        '+' => ExpansionOperator::UseAlternate,
+        '?' => ExpansionOperator::ErrorIfUnset,
+        _ => return None,
+    };
+
+    let replacement_start = after_colon + operator_char.len_utf8();
+    let mut depth = 0u32;
+    let mut escaped = false;
+    for (offset, char) in raw_value[replacement_start..].char_indices() {
+        let at = replacement_start + offset;
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match char {
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' if depth == 0 => {
+                return Some(ScannedExpansion {
+                    operator,
+                    replacement_range: replacement_start..at,
+                    close_index: at,
+                })
+            }
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The result of scanning a `$((expression))` arithmetic expansion, starting right after the
+/// opening `((` has been consumed.
+struct ScannedArithmetic {
+    expression_range: Range<usize>,
+    close_index: usize,
+}
+
+/// Given the byte index right after a `$((`, scan ahead to find the `))` that closes it,
+/// tracking ordinary paren depth so that inner parentheses in the arithmetic expression
+/// (eg. `$((2 * (1 + 1)))`) don't get mistaken for the closing `))`.
+///
+/// Returns `None` if the expansion is never closed.
+fn scan_arithmetic_expression(raw_value: &str, expression_start: usize) -> Option<ScannedArithmetic> {
+    let mut depth = 0u32;
+    for (offset, char) in raw_value[expression_start..].char_indices() {
+        let at = expression_start + offset;
+        match char {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => {
+                let after = at + ')'.len_utf8();
+                if raw_value[after..].starts_with(')') {
+                    return Some(ScannedArithmetic {
+                        expression_range: expression_start..at,
+                        close_index: after,
+                    });
+                }
+                // A single ')' here isn't the closing "))" we're looking for (and isn't
+                // balanced by an opening one either); just leave it as part of the expression.
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
 /// Display an error message for an invalid string pointing at the problematic section.
 ///
 /// This function is smart about where it puts the error message in relation to the arrows that
-/// point at the error.
-fn print_parse_error(
+/// point at the error. Kept `pub(crate)` so that callers of `parse_value` can render the
+/// `ParseError`s it returns using the same format as the diagnostics it prints itself.
+pub(crate) fn print_parse_error(
     log_level: log::Level,
     line: &str,
     index: RangeInclusive<usize>,
     error_description: &str,
+    note: Option<&str>,
 ) {
     if log::log_enabled!(log_level) {
         // The arrows that point at the section that is a problem.
@@ -485,8 +911,17 @@ fn print_parse_error(
         // Use color in error messages.
         use colored::Colorize;
 
-        // Finally, display the error message.
-        log!(log_level, "{}\n{}\n", line, full_error_message.red());
+        // Finally, display the error message, along with a suggestion if one was given.
+        match note {
+            Some(note) => log!(
+                log_level,
+                "{}\n{}\n{}\n",
+                line,
+                full_error_message.red(),
+                note
+            ),
+            None => log!(log_level, "{}\n{}\n", line, full_error_message.red()),
+        }
     }
 }
 
@@ -506,11 +941,12 @@ mod test_parsing {
             .iter()
             .map(|(kind, value)| ValuePart {
                 kind: *kind,
-                value: value.to_string(),
+                value: Cow::Borrowed(*value),
+                expansion: None,
             })
             .collect();
         // Compute the value that we get.
-        let parsed_value = parse_value(value);
+        let parsed_value = parse_value(value).expect("Expected a successfully parsed value");
 
         // Now check to see if it's what we expected.
         assert_eq!(parsed_value, res, "Check how {} parses", value);
@@ -547,6 +983,40 @@ mod test_parsing {
         )
     }
 
+    #[test]
+    fn test_ndots_three() {
+        assert_parses(
+            "${XDG_DATA_HOME}/.../share",
+            vec![(ShellVariable, "XDG_DATA_HOME"), (Literal, "/../../share")],
+        )
+    }
+
+    #[test]
+    fn test_ndots_four() {
+        assert_parses(
+            "${XDG_DATA_HOME}/..../share",
+            vec![
+                (ShellVariable, "XDG_DATA_HOME"),
+                (Literal, "/../../../share"),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_ndots_standalone() {
+        assert_parses("...", vec![(Literal, "../..")])
+    }
+
+    #[test]
+    fn test_ndots_one_and_two_dots_untouched() {
+        assert_parses("../foo/./bar", vec![(Literal, "../foo/./bar")])
+    }
+
+    #[test]
+    fn test_ndots_not_a_whole_segment() {
+        assert_parses("foo...bar", vec![(Literal, "foo...bar")])
+    }
+
     #[test]
     fn test_multiple() {
         assert_parses(
@@ -585,7 +1055,234 @@ mod test_parsing {
 
     #[test]
     fn test_nesting() {
-        // You shouldn't be able to nest special modes.
+        // A literal (escaped) '$(' and ')' inside a command aren't treated as a nested command,
+        // just carried through as the characters they were escaped to.
         assert_parses(r"$(echo ${$\(\)})", vec![(ShellCommand, "echo ${$()}")])
     }
+
+    #[test]
+    fn test_nested_command_substitution() {
+        // An unescaped, genuinely nested `$(...)` isn't cut short at its inner `)`: the whole
+        // thing is carried through as the outer command's text, and the target shell's own
+        // `eval` re-evaluates the nested substitution at runtime.
+        assert_parses(
+            "$(echo $(uname -m))",
+            vec![(ShellCommand, "echo $(uname -m)")],
+        )
+    }
+
+    #[test]
+    fn test_nested_command_substitution_multiple_levels() {
+        assert_parses(
+            "$(echo $(echo $(uname -m)))",
+            vec![(ShellCommand, "echo $(echo $(uname -m))")],
+        )
+    }
+
+    #[test]
+    fn test_nested_arithmetic_in_command() {
+        assert_parses(
+            "$(echo $((1 + 1)))",
+            vec![(ShellCommand, "echo $((1 + 1))")],
+        )
+    }
+
+    #[test]
+    fn test_nested_variable_in_command() {
+        assert_parses(
+            "$(dirname ${CONFIG})",
+            vec![(ShellCommand, "dirname ${CONFIG}")],
+        )
+    }
+
+    #[test]
+    fn test_nested_command_substitution_unclosed() {
+        let errors = parse_value("$(echo $(uname -m)").expect_err("should not parse");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Fatal: Unclosed shell statement!");
+    }
+
+    #[test]
+    fn test_expansion_use_default() {
+        let parsed =
+            parse_value("${XDG_DATA_HOME:-$HOME/.local/share}").expect("should parse");
+        assert_eq!(
+            parsed,
+            vec![ValuePart {
+                kind: ShellVariable,
+                value: Cow::Borrowed("XDG_DATA_HOME"),
+                expansion: Some(Expansion {
+                    operator: ExpansionOperator::UseDefault,
+                    replacement: vec![
+                        ValuePart {
+                            kind: ShellVariable,
+                            value: Cow::Borrowed("HOME"),
+                            expansion: None,
+                        },
+                        ValuePart {
+                            kind: Literal,
+                            value: Cow::Borrowed("/.local/share"),
+                            expansion: None,
+                        },
+                    ],
+                }),
+            }],
+        )
+    }
+
+    #[test]
+    fn test_expansion_use_alternate() {
+        let parsed = parse_value("${DEBUG:+--verbose}").expect("should parse");
+        assert_eq!(
+            parsed,
+            vec![ValuePart {
+                kind: ShellVariable,
+                value: Cow::Borrowed("DEBUG"),
+                expansion: Some(Expansion {
+                    operator: ExpansionOperator::UseAlternate,
+                    replacement: vec![ValuePart {
+                        kind: Literal,
+                        value: Cow::Borrowed("--verbose"),
+                        expansion: None,
+                    }],
+                }),
+            }],
+        )
+    }
+
+    #[test]
+    fn test_expansion_assign_default() {
+        let parsed = parse_value("${XDG_CACHE_HOME:=$HOME/.cache}").expect("should parse");
+        assert_eq!(
+            parsed,
+            vec![ValuePart {
+                kind: ShellVariable,
+                value: Cow::Borrowed("XDG_CACHE_HOME"),
+                expansion: Some(Expansion {
+                    operator: ExpansionOperator::AssignDefault,
+                    replacement: vec![
+                        ValuePart {
+                            kind: ShellVariable,
+                            value: Cow::Borrowed("HOME"),
+                            expansion: None,
+                        },
+                        ValuePart {
+                            kind: Literal,
+                            value: Cow::Borrowed("/.cache"),
+                            expansion: None,
+                        },
+                    ],
+                }),
+            }],
+        )
+    }
+
+    #[test]
+    fn test_expansion_error_if_unset() {
+        let parsed = parse_value("${API_KEY:?API_KEY must be set}").expect("should parse");
+        assert_eq!(
+            parsed,
+            vec![ValuePart {
+                kind: ShellVariable,
+                value: Cow::Borrowed("API_KEY"),
+                expansion: Some(Expansion {
+                    operator: ExpansionOperator::ErrorIfUnset,
+                    replacement: vec![ValuePart {
+                        kind: Literal,
+                        value: Cow::Borrowed("API_KEY must be set"),
+                        expansion: None,
+                    }],
+                }),
+            }],
+        )
+    }
+
+    #[test]
+    fn test_expansion_followed_by_literal() {
+        // `assert_parses` always expects `expansion: None`, so it can't express a part that
+        // actually has one - build the full expected `Vec<ValuePart>` by hand instead, the same
+        // way `test_expansion_use_default` and its neighbors do.
+        let parsed = parse_value("${EDITOR:-vim}-wrapper").expect("should parse");
+        assert_eq!(
+            parsed,
+            vec![
+                ValuePart {
+                    kind: ShellVariable,
+                    value: Cow::Borrowed("EDITOR"),
+                    expansion: Some(Expansion {
+                        operator: ExpansionOperator::UseDefault,
+                        replacement: vec![ValuePart {
+                            kind: Literal,
+                            value: Cow::Borrowed("vim"),
+                            expansion: None,
+                        }],
+                    }),
+                },
+                ValuePart {
+                    kind: Literal,
+                    value: Cow::Borrowed("-wrapper"),
+                    expansion: None,
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_parses("$((NPROC * 2))", vec![(ShellArithmetic, "NPROC * 2")])
+    }
+
+    #[test]
+    fn test_arithmetic_nested_parens() {
+        assert_parses(
+            "$((2 * (1 + 1)))",
+            vec![(ShellArithmetic, "2 * (1 + 1)")],
+        )
+    }
+
+    #[test]
+    fn test_arithmetic_followed_by_literal() {
+        assert_parses(
+            "Threads: $((NPROC * 2))!",
+            vec![
+                (Literal, "Threads: "),
+                (ShellArithmetic, "NPROC * 2"),
+                (Literal, "!"),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_arithmetic_unclosed() {
+        let errors = parse_value("$((NPROC * 2)").expect_err("should not parse");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Fatal: Unclosed shell statement!");
+    }
+
+    #[test]
+    fn test_confusable_fullwidth_underscore() {
+        let errors = parse_value("${XDG_DATA＿HOME}").expect_err("should not parse");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].note.as_deref(),
+            Some("Did you mean '_' instead of '＿'?")
+        );
+    }
+
+    #[test]
+    fn test_confusable_curly_quote() {
+        let errors = parse_value("${PATH’}").expect_err("should not parse");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].note.as_deref(),
+            Some("Did you mean ''' instead of '’'?")
+        );
+    }
+
+    #[test]
+    fn test_no_confusable_suggestion_for_unrelated_character() {
+        let errors = parse_value("${PATH!}").expect_err("should not parse");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].note, None);
+    }
 }
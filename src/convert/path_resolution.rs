@@ -0,0 +1,161 @@
+// Copyright 2022 Ethan Kinnear
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Eager path resolution, performed at generation time instead of being deferred to the target
+//! shell: rather than emitting `$HOME/foo`, resolve `~`/relative values into a concrete absolute
+//! path and bake that string directly into the generated script.
+//!
+//! Mirrors the two resolution levels of nushell's `nu-path` crate:
+//!
+//! * [`absolutize`] lexically joins a value against a base directory and collapses `.`/`..`
+//!   segments without touching the filesystem, so it works for paths that don't exist yet.
+//! * [`canonicalize`] does the same, but also resolves symlinks via the filesystem, so it
+//!   requires the path to actually exist.
+
+use crate::cli::PathResolutionMode;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+impl PathResolutionMode {
+    /// Resolve `path` (relative to `base` if it isn't already absolute) according to this mode.
+    ///
+    /// `canonicalize` requires `path` to exist, so it can fail; `absolutize` never does.
+    pub(crate) fn resolve(self, path: &str, base: &Path) -> io::Result<PathBuf> {
+        match self {
+            PathResolutionMode::Absolutize => Ok(absolutize(path, base)),
+            PathResolutionMode::Canonicalize => canonicalize(path, base),
+        }
+    }
+}
+
+/// Lexically join `path` against `base` (if `path` isn't already absolute), collapsing `.`/`..`
+/// segments without touching the filesystem.
+///
+/// A trailing slash is preserved only if `path` itself has one and contains no `.`/`..`
+/// segments, matching `nu-path`'s behavior.
+pub(crate) fn absolutize(path: &str, base: &Path) -> PathBuf {
+    let has_trailing_slash = path.len() > 1 && path.ends_with('/');
+    // Checked on `path` itself: `Path::components` silently normalizes away a `.` that isn't at
+    // the start of the path, so after joining onto `base` it would never show up below.
+    let saw_dot_segment = path.split('/').any(|segment| segment == "." || segment == "..");
+
+    let joined = base.join(path);
+    let mut resolved = Vec::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => resolved.push(other),
+        }
+    }
+
+    let mut resolved: PathBuf = resolved.into_iter().collect();
+    if has_trailing_slash && !saw_dot_segment {
+        let mut with_slash = resolved.into_os_string();
+        with_slash.push("/");
+        resolved = PathBuf::from(with_slash);
+    }
+    resolved
+}
+
+/// Like [`absolutize`], but also resolves symlinks via the filesystem. Requires `path` to exist.
+pub(crate) fn canonicalize(path: &str, base: &Path) -> io::Result<PathBuf> {
+    std::fs::canonicalize(base.join(path))
+}
+
+/// Find the home directory for a `~`/`~user` token (`user` is `""` for a bare `~`).
+///
+/// There's no portable API for looking up another user's home directory, so `~user` falls back
+/// to the conventional Unix layout (a sibling of the current user's home directory) instead.
+pub(crate) fn resolve_home(user: &str) -> Option<PathBuf> {
+    if user.is_empty() {
+        dirs::home_dir()
+    } else {
+        dirs::home_dir()?.parent().map(|users| users.join(user))
+    }
+}
+
+#[cfg(test)]
+mod test_path_resolution {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_absolutize_tilde() {
+        assert_eq!(
+            absolutize("", Path::new("/home/superatomic")),
+            PathBuf::from("/home/superatomic"),
+        );
+    }
+
+    #[test]
+    fn test_absolutize_tilde_user() {
+        assert_eq!(
+            absolutize("", Path::new("/home/other")),
+            PathBuf::from("/home/other"),
+        );
+    }
+
+    #[test]
+    fn test_absolutize_current_dir() {
+        assert_eq!(
+            absolutize("./foo", Path::new("/home/superatomic")),
+            PathBuf::from("/home/superatomic/foo"),
+        );
+    }
+
+    #[test]
+    fn test_absolutize_parent_dir() {
+        assert_eq!(
+            absolutize("../foo", Path::new("/home/superatomic/bin")),
+            PathBuf::from("/home/superatomic/foo"),
+        );
+    }
+
+    #[test]
+    fn test_absolutize_preserves_trailing_slash() {
+        // Compared as strings: `PathBuf` equality ignores a trailing slash.
+        assert_eq!(
+            absolutize("foo/", Path::new("/home/superatomic")).into_os_string(),
+            std::ffi::OsString::from("/home/superatomic/foo/"),
+        );
+    }
+
+    #[test]
+    fn test_absolutize_drops_trailing_slash_with_dot_segments() {
+        // The trailing slash is only preserved when there were no `.`/`..` segments to collapse.
+        assert_eq!(
+            absolutize("./foo/", Path::new("/home/superatomic")).into_os_string(),
+            std::ffi::OsString::from("/home/superatomic/foo"),
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_current_dir() {
+        // `base` itself always exists and has no symlinks to resolve relative to itself, so
+        // canonicalizing "." against it is equivalent to absolutizing "." against it.
+        let base = std::env::current_dir().expect("should have a current directory");
+        assert_eq!(
+            canonicalize(".", &base).expect("should resolve"),
+            absolutize(".", &base),
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_nonexistent_path_fails() {
+        let base = std::env::current_dir().expect("should have a current directory");
+        assert!(canonicalize("this/path/does/not/exist", &base).is_err());
+    }
+}
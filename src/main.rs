@@ -27,19 +27,26 @@
 #![forbid(unsafe_code)]
 
 mod cli;
+mod complete;
 mod convert;
 mod structure;
 
 #[macro_use]
 extern crate log;
 
-use clap::{Parser, ValueEnum};
+use clap::{CommandFactory, Parser, ValueEnum};
+use clap_mangen::Man;
 use human_panic::setup_panic;
 use indexmap::IndexMap;
-use std::io::{stdin, ErrorKind, Read};
-use std::{env, fs, path::PathBuf, process::exit, string::String};
-
-use crate::cli::{Cli, Shell};
+use std::io::{stdin, stdout, ErrorKind, Read};
+use std::{
+    env, fs, mem,
+    path::{Path, PathBuf},
+    process::exit,
+    string::String,
+};
+
+use crate::cli::{Cli, Command, ConfigFormat, Shell};
 use crate::structure::{ConfigFile, EnvVariableOption, EnvVariableValue};
 
 fn main() {
@@ -61,44 +68,40 @@ fn main() {
         .format_indent(Some(8)) // Aligns the first line with the other lines
         .init();
 
-    // Pipe if `cli_options.pipe` is used or if `cli_options.file` is used and equal to "-".
+    // Handle subcommands that don't generate a shell script before doing anything else.
+    if let Some(command) = &cli_options.command {
+        run_command(command);
+        return;
+    }
+
+    // Pipe if `cli_options.pipe` is used or if any `cli_options.file` is equal to "-".
     let pipe = cli_options.pipe
-        || cli_options
-            .file
-            .as_ref()
-            .map_or(false, |x| x.to_string_lossy() == "-");
+        || cli_options.file.iter().any(|x| x.to_string_lossy() == "-");
 
-    let (toml_string, file_name) = if pipe {
+    let file_data = if pipe {
         // If --pipe was specified, use that as the direct toml.
-        (read_stdin(), String::from("<STDIN>"))
-    } else if let Some(text) = cli_options.text {
-        // If --text was specified, use that. Otherwise, get the file and read from it.
-        (text, String::from("<STRING>"))
+        info!("Reading file data from <STDIN>");
+        let format = cli_options.format.unwrap_or(ConfigFormat::Toml);
+        parse_config_file(read_stdin(), "<STDIN>", format)
+    } else if let Some(text) = &cli_options.text {
+        // If --text was specified, use that. Otherwise, get the file(s) and read from them.
+        info!("Reading file data from <STRING>");
+        let format = cli_options.format.unwrap_or(ConfigFormat::Toml);
+        parse_config_file(text.clone(), "<STRING>", format)
     } else {
-        // Otherwise, read from the chosen file.
-        read_config_file(&cli_options)
-    };
-    info!("Reading file data from {}", file_name);
-
-    // Load file data from the TOML file.
-    let file_data = match ConfigFile::load(toml_string) {
-        Ok(valid_toml) => valid_toml,
-
-        // The file isn't a valid TOML format!
-        Err(e) => {
-            // Display the error and exit.
-            error!(
-                "The file {} is not in a valid TOML format,\n\
-                 or it is not in the form xshe is expecting.",
-                file_name
-            );
-            error!("{}", e);
-            exit(exitcode::CONFIG)
-        }
+        let paths = config_paths(&cli_options);
+        let file_option_set = !cli_options.file.is_empty();
+        info!(
+            "Reading file data from {}",
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+        load_layered_config(&paths, file_option_set, cli_options.format)
     };
 
-    let shell: Shell = cli_options.shell;
-
     // Deprecation warning
     if file_data.shell.is_some() {
         warn!(
@@ -108,15 +111,122 @@ fn main() {
         );
     }
 
-    // Output the file data converted to the correct shell format to the standard output.
-    let output = convert::to_shell_source(&file_data.vars, &shell);
-    print!("{}", output);
+    // --check validates the config instead of generating a script, so it doesn't need a shell.
+    if cli_options.check {
+        run_check(&file_data);
+        return;
+    }
+
+    // Resolve the base variables plus the selected `--profile` overlay, if any.
+    let vars = file_data
+        .resolve_profile(cli_options.profile.as_deref())
+        .unwrap_or_else(|e| {
+            error!("{}", e);
+            exit(exitcode::CONFIG)
+        });
+
+    // --dotenv generates a plain .env file instead of a script for a particular shell, so it
+    // doesn't need a shell either, and has no use for aliases or the deprecated [shell.NAME] form.
+    if cli_options.dotenv {
+        let output = convert::to_dotenv_source(&vars);
+        write_output(&output, cli_options.output.as_deref());
+        return;
+    }
+
+    // `shell` is only absent when a subcommand, --check, or --dotenv was used, all handled above.
+    let shell = cli_options.shell.expect("a shell is required");
+
+    // --dump inspects the fully-resolved, pre-shell-syntax mapping instead of generating a
+    // script, as a way to check how a config resolves without diffing generated shell scripts.
+    if let Some(format) = cli_options.dump {
+        let resolved = convert::resolve_for_shell(&vars, &shell);
+        let output = dump_resolved(&resolved, format);
+        write_output(&output, cli_options.output.as_deref());
+        return;
+    }
+
+    // Output the file data converted to the correct shell format.
+    let base_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let mut output = convert::to_shell_source(&vars, &shell, cli_options.resolve_paths, &base_dir);
+    if let Some(aliases) = &file_data.alias {
+        output.push_str(&convert::to_alias_source(aliases, &shell));
+    }
+    write_output(&output, cli_options.output.as_deref());
 
     // Retain compatibility with deprecated https://github.com/superatomic/xshe/issues/30
-    deprecated_to_specific_shell_source(&file_data, &shell);
+    deprecated_to_specific_shell_source(&file_data, &shell, cli_options.resolve_paths, &base_dir);
+}
+
+/// Validate `file_data` instead of generating a script, as used by `--check`. Prints every
+/// problem found and exits non-zero if there were any; otherwise exits zero silently.
+fn run_check(file_data: &ConfigFile) {
+    let issues = convert::check_config(file_data);
+    if issues.is_empty() {
+        return;
+    }
+    for issue in &issues {
+        error!("{}", issue);
+    }
+    error!(
+        "Found {} problem{} while checking the config",
+        issues.len(),
+        if issues.len() == 1 { "" } else { "s" }
+    );
+    exit(exitcode::DATAERR);
+}
+
+/// Serialize `resolved` (see `convert::resolve_for_shell`) as `format`, as used by `--dump`.
+fn dump_resolved(resolved: &IndexMap<String, EnvVariableValue>, format: ConfigFormat) -> String {
+    match format {
+        ConfigFormat::Toml => {
+            toml::to_string_pretty(resolved).expect("a resolved config serializes to TOML")
+        }
+        ConfigFormat::Json => {
+            serde_json::to_string_pretty(resolved).expect("a resolved config serializes to JSON")
+        }
+        ConfigFormat::Yaml => {
+            serde_yaml::to_string(resolved).expect("a resolved config serializes to YAML")
+        }
+    }
+}
+
+/// Write `output` to `destination` if one was given with `-o`/`--output`, otherwise print it to
+/// the standard output.
+fn write_output(output: &str, destination: Option<&Path>) {
+    match destination {
+        Some(path) => fs::write(path, output)
+            .unwrap_or_else(|e| exit_with_file_error(e.kind(), &path.to_string_lossy(), true)),
+        None => print!("{}", output),
+    }
 }
 
-fn deprecated_to_specific_shell_source(file_data: &ConfigFile, shell: &Shell) {
+fn run_command(command: &Command) {
+    //! Run a subcommand that doesn't generate an environment variable script.
+    match command {
+        Command::Completions { shell } => {
+            let mut cmd = Cli::command();
+            let name = cmd.get_name().to_string();
+            clap_complete::generate(*shell, &mut cmd, name, &mut stdout());
+        }
+        Command::Manpage => {
+            let cmd = Cli::command();
+            Man::new(cmd)
+                .render(&mut stdout())
+                .unwrap_or_else(|e| error!("Could not render the man page: {}", e));
+        }
+        Command::CompleteVars { word, file } => {
+            let config_path = file.clone().unwrap_or_else(get_file_path_default);
+            complete::complete_variable_names(&config_path, word);
+        }
+    }
+}
+
+fn deprecated_to_specific_shell_source(
+    file_data: &ConfigFile,
+    shell: &Shell,
+    resolve_paths: Option<cli::PathResolutionMode>,
+    base_dir: &PathBuf,
+) {
     // Output the any specific variables for the shell the same way, if they exist.
     // This behavior is deprecated.
     if let Some(specific_vars) = get_specific_shell(shell, file_data) {
@@ -127,26 +237,143 @@ fn deprecated_to_specific_shell_source(file_data: &ConfigFile, shell: &Shell) {
             .map(|(key, value)| (key.to_owned(), EnvVariableOption::General(value.to_owned())))
             .collect();
 
-        let shell_specific_output = convert::to_shell_source(&wrap_specific_vars, shell);
+        let shell_specific_output =
+            convert::to_shell_source(&wrap_specific_vars, shell, resolve_paths, base_dir);
 
         print!("{:?}", shell_specific_output);
     };
 }
 
-fn read_config_file(cli_options: &Cli) -> (String, String) {
-    //! Read from the config file that should be selected based on the `--file` option.
-    // Get the target TOML file with the environment variables.
-    // If they are not manually set, use the XDG Base Directory Specification defaults.
-    let raw_file = &cli_options.file;
-    let file = &raw_file.clone().unwrap_or_else(get_file_path_default);
-
-    // Read the contents of the file.
-    // Exit with an error message and exit code if an error occurs.
-    let toml_string = match fs::read_to_string(file) {
-        Ok(string) => string,
-        Err(e) => exit_with_file_error(e.kind(), &file.to_string_lossy(), raw_file.is_some()),
-    };
-    (toml_string, file.display().to_string())
+fn config_paths(cli_options: &Cli) -> Vec<PathBuf> {
+    //! Get the list of config files to read and layer together, based on the `--file` option.
+    //! If none were given, fall back to the single XDG Base Directory Specification default.
+    if cli_options.file.is_empty() {
+        vec![get_file_path_default()]
+    } else {
+        cli_options.file.clone()
+    }
+}
+
+fn parse_config_file(content: String, source_name: &str, format: ConfigFormat) -> ConfigFile {
+    //! Parse a config string into a `ConfigFile`, or display an error and exit if it's invalid.
+    match ConfigFile::load(content, format) {
+        Ok(valid_config) => valid_config,
+
+        // The file isn't valid in the format it was read as!
+        Err(e) => {
+            // Display the error and exit.
+            let format_name = format
+                .to_possible_value()
+                .expect("`ConfigFormat` has no skipped variants")
+                .get_name()
+                .to_uppercase();
+            error!(
+                "The file {} is not in a valid {} format,\n\
+                 or it is not in the form xshe is expecting.",
+                source_name, format_name,
+            );
+            error!("{}", e);
+            exit(exitcode::CONFIG)
+        }
+    }
+}
+
+/// Guess a config file's serialization format from its extension, falling back to TOML for an
+/// unrecognized or missing extension.
+fn detect_format(path: &Path) -> ConfigFormat {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+        Some(ext) if ext.eq_ignore_ascii_case("yaml") => ConfigFormat::Yaml,
+        Some(ext) if ext.eq_ignore_ascii_case("yml") => ConfigFormat::Yaml,
+        _ => ConfigFormat::Toml,
+    }
+}
+
+fn load_layered_config(
+    paths: &[PathBuf],
+    file_option_set: bool,
+    format_override: Option<ConfigFormat>,
+) -> ConfigFile {
+    //! Read and parse each of `paths` (following their own `include`s), and merge them together
+    //! in order, so that later paths win over earlier ones on a per-variable basis.
+    //!
+    //! `file_option_set` is only used to pick the right help message if the first layer is
+    //! missing: `false` means `paths` is the single XDG default location, `true` means it came
+    //! from an explicit `--file`. Files pulled in afterwards, whether further `--file` layers or
+    //! an `include`, are always explicitly named somewhere, so they always use the `--file`
+    //! wording if missing.
+    let mut merged: Option<ConfigFile> = None;
+    for (index, path) in paths.iter().enumerate() {
+        let file_data = load_config_file_with_includes(
+            path,
+            &mut Vec::new(),
+            file_option_set || index > 0,
+            format_override,
+        );
+        match &mut merged {
+            Some(accumulated) => accumulated.merge_from(file_data),
+            None => merged = Some(file_data),
+        }
+    }
+    merged.expect("`paths` is never empty: `config_paths` always returns at least one path")
+}
+
+fn load_config_file_with_includes(
+    path: &Path,
+    visiting: &mut Vec<PathBuf>,
+    file_option_set: bool,
+    format_override: Option<ConfigFormat>,
+) -> ConfigFile {
+    //! Read and parse `path`, then recursively load and merge in its own `include = [...]` files
+    //! (resolved relative to `path`'s directory), with the file's own tables winning over
+    //! anything it includes. `visiting` tracks the chain of files being loaded so an include
+    //! cycle can be reported instead of recursing forever.
+    //!
+    //! Each file's format is `format_override` if one was explicitly given with `--format`,
+    //! otherwise it's auto-detected from that file's own extension - so a TOML file can `include`
+    //! a YAML one, or vice versa.
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if visiting.contains(&canonical) {
+        let mut chain = visiting.clone();
+        chain.push(canonical);
+        let chain = chain.iter().map(|p| p.display().to_string()).collect::<Vec<_>>();
+        error!("Include cycle detected:\n{}", chain.join("\nincludes -> "));
+        exit(exitcode::CONFIG);
+    }
+    visiting.push(canonical);
+
+    let format = format_override.unwrap_or_else(|| detect_format(path));
+    let content = fs::read_to_string(path).unwrap_or_else(|e| {
+        exit_with_file_error(e.kind(), &path.to_string_lossy(), file_option_set)
+    });
+    let mut file_data = parse_config_file(content, &path.display().to_string(), format);
+
+    let includes = mem::take(&mut file_data.include);
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut merged: Option<ConfigFile> = None;
+    for include in &includes {
+        let included_data = load_config_file_with_includes(
+            &base_dir.join(include),
+            visiting,
+            true,
+            format_override,
+        );
+        match &mut merged {
+            Some(accumulated) => accumulated.merge_from(included_data),
+            None => merged = Some(included_data),
+        }
+    }
+
+    visiting.pop();
+
+    match merged {
+        Some(mut accumulated) => {
+            accumulated.merge_from(file_data);
+            accumulated
+        }
+        None => file_data,
+    }
 }
 
 fn exit_with_file_error(kind: ErrorKind, file_name: &str, file_option_set: bool) -> ! {
@@ -13,42 +13,275 @@
 
 //! Defines the structure of the TOML configuration file.
 
+use crate::cli::ConfigFormat;
 use indexmap::IndexMap;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::string::String;
 
 pub(crate) type EnvironmentVariables = IndexMap<String, EnvVariableOption>;
 
 /// The TOML file to load environment variables from.
-#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub(crate) struct ConfigFile {
     #[serde(flatten)]
     pub(crate) vars: EnvironmentVariables,
 
+    /// Environment-specific overlays, selected via `--profile`/`XSHE_PROFILE`.
+    ///
+    /// Each `[env.NAME]` table is merged on top of `vars` when that profile is selected; see
+    /// [`ConfigFile::resolve_profile`].
+    #[serde(default)]
+    pub(crate) env: Option<IndexMap<String, EnvironmentVariables>>,
+
+    /// Other TOML files to merge in underneath this one's own tables, resolved relative to the
+    /// directory this file was loaded from. See `main::load_config_file_with_includes`, which
+    /// does the actual reading, cycle detection, and merging - this field only carries the raw
+    /// paths out of the parsed TOML.
+    #[serde(default)]
+    pub(crate) include: Vec<String>,
+
+    /// Command aliases, converted per-shell by `convert::to_alias_source`. Supports the same
+    /// `General`/`Specific` shell-targeting as `vars` does, so `ll.fish = "..."` only applies to
+    /// fish - see [`AliasOption`].
+    #[serde(default)]
+    pub(crate) alias: Option<IndexMap<String, AliasOption>>,
+
     // Deprecated
     pub(crate) shell: Option<HashMap<String, IndexMap<String, EnvVariableValue>>>,
 }
 
 impl ConfigFile {
-    pub(crate) fn load(toml_string: String) -> Result<ConfigFile, toml::de::Error> {
-        toml::from_str(&toml_string)
+    /// Parse `content` as a `ConfigFile`, deserializing it according to `format`.
+    pub(crate) fn load(
+        content: String,
+        format: ConfigFormat,
+    ) -> Result<ConfigFile, ConfigParseError> {
+        match format {
+            ConfigFormat::Toml => toml::from_str(&content).map_err(ConfigParseError::Toml),
+            ConfigFormat::Json => serde_json::from_str(&content).map_err(ConfigParseError::Json),
+            ConfigFormat::Yaml => serde_yaml::from_str(&content).map_err(ConfigParseError::Yaml),
+        }
+    }
+
+    /// Resolve the variable map to generate a script from: the base `vars`, overlaid with the
+    /// `[env.NAME]` table matching `profile` if one was requested.
+    ///
+    /// Keys present in the profile's table overwrite the base value at that key; keys the profile
+    /// doesn't mention keep their base value. Passing `None` (no `--profile` given) just returns
+    /// `vars` unchanged.
+    pub(crate) fn resolve_profile(
+        &self,
+        profile: Option<&str>,
+    ) -> Result<EnvironmentVariables, UnknownProfileError> {
+        let Some(profile) = profile else {
+            return Ok(self.vars.clone());
+        };
+
+        let overlay = self
+            .env
+            .as_ref()
+            .and_then(|env| env.get(profile))
+            .ok_or_else(|| UnknownProfileError::new(profile, self.env.as_ref()))?;
+
+        let mut resolved = self.vars.clone();
+        for (key, value) in overlay {
+            resolved.insert(key.clone(), value.clone());
+        }
+        Ok(resolved)
+    }
+
+    /// Merge `other`'s tables into `self` in place, with `other`'s entries winning conflicts.
+    ///
+    /// The merge happens per individual variable (and, inside `env`/`shell`, per individual
+    /// profile/shell entry), not by replacing a whole table - so a layered-in file only needs to
+    /// mention the variables it actually changes.
+    pub(crate) fn merge_from(&mut self, other: ConfigFile) {
+        for (key, value) in other.vars {
+            self.vars.insert(key, value);
+        }
+
+        if let Some(other_env) = other.env {
+            let self_env = self.env.get_or_insert_with(IndexMap::new);
+            for (profile, profile_vars) in other_env {
+                let merged_profile = self_env.entry(profile).or_default();
+                for (key, value) in profile_vars {
+                    merged_profile.insert(key, value);
+                }
+            }
+        }
+
+        if let Some(other_alias) = other.alias {
+            let self_alias = self.alias.get_or_insert_with(IndexMap::new);
+            for (name, value) in other_alias {
+                self_alias.insert(name, value);
+            }
+        }
+
+        if let Some(other_shell) = other.shell {
+            let self_shell = self.shell.get_or_insert_with(HashMap::new);
+            for (shell_name, shell_vars) in other_shell {
+                let merged_shell = self_shell.entry(shell_name).or_default();
+                for (key, value) in shell_vars {
+                    merged_shell.insert(key, value);
+                }
+            }
+        }
     }
 }
 
-#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+/// Returned by [`ConfigFile::resolve_profile`] when `--profile` names an environment that has no
+/// matching `[env.NAME]` table in the config.
+#[derive(Debug)]
+pub(crate) struct UnknownProfileError {
+    profile: String,
+    known_profiles: Vec<String>,
+}
+
+impl UnknownProfileError {
+    fn new(profile: &str, env: Option<&IndexMap<String, EnvironmentVariables>>) -> Self {
+        UnknownProfileError {
+            profile: profile.to_string(),
+            known_profiles: env.map_or_else(Vec::new, |env| env.keys().cloned().collect()),
+        }
+    }
+}
+
+impl std::fmt::Display for UnknownProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No [env.{}] profile is defined in this config", self.profile)?;
+        if self.known_profiles.is_empty() {
+            write!(f, " (it defines no profiles at all)")
+        } else {
+            write!(f, " (known profiles: {})", self.known_profiles.join(", "))
+        }
+    }
+}
+
+/// Returned by [`ConfigFile::load`] when `content` isn't valid in the format it was read as.
+#[derive(Debug)]
+pub(crate) enum ConfigParseError {
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl std::fmt::Display for ConfigParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigParseError::Toml(e) => write!(f, "{}", e),
+            ConfigParseError::Json(e) => write!(f, "{}", e),
+            ConfigParseError::Yaml(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub(crate) enum EnvVariableOption {
+    General(EnvVariableValue),
+    Specific(IndexMap<String, OsOption>),
+}
+
+/// The value of one entry in an `EnvVariableOption::Specific` table: either a plain value that
+/// applies on every OS, or a table specializing it further by target OS (`linux`/`macos`/
+/// `windows`, matching `std::env::consts::OS`), with the same `_` catch-all convention the outer
+/// shell-keyed table uses. Lets a variable be specialized by OS alone (`FOO.macos = "..."`, a
+/// sibling of the shell keys) or by shell-then-OS (`FOO.bash.macos = "..."`), covering both
+/// "this only differs by OS" and "this only differs by OS, and only for one particular shell".
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum OsOption {
     General(EnvVariableValue),
     Specific(IndexMap<String, EnvVariableValue>),
 }
 
 /// Enum of possible environment variable value types.
-#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+///
+/// Also derives `Serialize`, used by `convert::resolve_for_shell`'s `--dump` output - the same
+/// untagged shape round-trips back through `Deserialize` unchanged.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub(crate) enum EnvVariableValue {
     Array(Vec<Vec<String>>),
+    /// A TOML offset/local date, time, or date-time, such as `2024-02-07T00:00:00Z` - rendered
+    /// verbatim via its own `Display` impl, which reproduces the RFC 3339 form.
+    Datetime(toml::value::Datetime),
+    /// Set a variable to `value` only if it isn't already set to something, written as an inline
+    /// table: `EDITOR = { default = "nvim" }` leaves an already-exported `EDITOR` untouched, and
+    /// only falls back to `nvim` if it's unset (or empty). This reuses the same `${NAME:=value}`
+    /// parameter-expansion machinery a literal `EDITOR = "${EDITOR:=nvim}"` string would already
+    /// go through (see `convert::render_expansion`), rather than a bespoke guard of its own.
+    Default {
+        #[serde(rename = "default")]
+        value: String,
+    },
+    Integer(i64),
+    Float(f64),
     Path(Vec<String>),
+    /// Prepend and/or append entries to an existing `PATH`-like variable instead of replacing it
+    /// outright, written as an inline table: `PATH = { prepend = ["/usr/local/bin"] }` emits
+    /// something like `export PATH='/usr/local/bin':"$PATH"` rather than clobbering whatever the
+    /// login shell already put there. Either list may be omitted; given together, `prepend`
+    /// entries land nearest the front of the existing value and `append` entries nearest the back.
+    PathModify(PathModify),
+    /// An explicit export attribute, written as an inline table:
+    /// `{ value = "1000", export = false }` sets a variable in the shell's own scope without
+    /// exporting it to child processes - useful for shell options like `HISTSIZE`. Omitting
+    /// `value` (`{ export = false }`) instead leaves whatever `NAME` is already set to untouched
+    /// and only strips (or restores) its export attribute, mirroring `export -n`/`export` in
+    /// Bash and Zsh.
+    Scoped {
+        #[serde(default)]
+        value: Option<String>,
+        export: bool,
+    },
     Set(bool),
     String(String),
 }
+
+/// The `prepend`/`append` lists of an [`EnvVariableValue::PathModify`] table.
+///
+/// Deserializes via `RawPathModify` instead of deriving `Deserialize` directly: both fields are
+/// optional, so naively deriving it would make this the first untagged variant of
+/// `EnvVariableValue` willing to match *any* inline table (including an empty one) - silently
+/// swallowing `Scoped`/`Specific` tables that happen to come later in that enum (or in
+/// `EnvVariableOption`/`OsOption`, one level up) instead of letting them fail over to their actual
+/// variant. The `TryFrom` below rejects a table naming neither field (or naming some other field
+/// entirely), so deserialization falls through to the next variant instead.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "RawPathModify")]
+pub(crate) struct PathModify {
+    pub(crate) prepend: Vec<String>,
+    pub(crate) append: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RawPathModify {
+    #[serde(default)]
+    prepend: Vec<String>,
+    #[serde(default)]
+    append: Vec<String>,
+}
+
+impl TryFrom<RawPathModify> for PathModify {
+    type Error = &'static str;
+
+    fn try_from(raw: RawPathModify) -> Result<Self, Self::Error> {
+        if raw.prepend.is_empty() && raw.append.is_empty() {
+            return Err("a `PathModify` table must set at least one of `prepend`/`append`");
+        }
+        Ok(PathModify { prepend: raw.prepend, append: raw.append })
+    }
+}
+
+/// A command alias's value: either the same command for every shell, or a per-shell table. Mirrors
+/// the `General`/`Specific` split of [`EnvVariableOption`], but for a plain command string rather
+/// than the richer [`EnvVariableValue`] - an alias body has no array/path/set form of its own.
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum AliasOption {
+    General(String),
+    Specific(IndexMap<String, String>),
+}